@@ -0,0 +1,13 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use linux_mirrors::archlinux::internal::ArchMirrors;
+
+const FIXTURE: &str = include_str!("../../assets/arch_mirrors.json");
+
+fn decode(c: &mut Criterion) {
+    c.bench_function("decode the bundled status", |b| {
+        b.iter(|| serde_json::from_str::<ArchMirrors>(black_box(FIXTURE)).unwrap())
+    });
+}
+
+criterion_group!(benches, decode);
+criterion_main!(benches);
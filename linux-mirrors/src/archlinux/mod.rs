@@ -1,29 +1,562 @@
+use chrono::{DateTime, Utc};
+use hyper::{body::Bytes, HeaderMap, Response, StatusCode};
+use log::warn;
+use serde::Deserialize;
+
 use self::internal::ArchMirrors;
-use crate::http2_client;
+use crate::{Cache, Client, Country, Entry, Error, MirrorProvider, Retry, RetryPolicy};
 
 use super::Result;
+mod query;
+pub mod rate;
 mod response;
-use hyper::body::Buf;
-pub use response::internal;
+pub use query::{MirrorQuery, SortBy};
+pub use response::{external, internal};
+
+const ARCHLINUX_URL: &str = "https://archlinux.org/";
+const STATUS_PATH: &str = "mirrors/status/json/";
+/// The version of the status schema this module understands
+pub const SCHEMA_VERSION: i64 = 3;
+/// The key the status is cached under
+const CACHE_KEY: &str = "archlinux-status";
+
+/// Mirrors listed on archlinux.org
+#[derive(Default, Debug, Clone)]
+pub struct ArchLinux {
+    client: Client,
+    cache: Option<Cache>,
+    retry: RetryPolicy,
+}
+
+impl ArchLinux {
+    /// Fetches the status with `client`, from its base url if it has one or archlinux.org
+    pub fn new(client: Client) -> Self {
+        Self {
+            client,
+            cache: None,
+            retry: RetryPolicy::default(),
+        }
+    }
+
+    /// Keeps the last good status in `cache` and only downloads it again when it has changed
+    pub fn with_cache(mut self, cache: Cache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Retries failed fetches as `retry` says, transient errors are tried 3 times by default
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Like [`MirrorProvider::fetch`], calling `on_retry` before each retry
+    pub async fn fetch_reporting(
+        &self,
+        mut on_retry: impl FnMut(&Retry<'_>) + Send,
+    ) -> Result<ArchMirrors> {
+        let cached = self.cache_entry();
+        let validators = match &cached {
+            Some(entry) => entry.validators(),
+            None => HeaderMap::new(),
+        };
+        let res = self.request(validators, &mut on_retry).await?;
+        let res = match (res.status(), cached) {
+            (StatusCode::NOT_MODIFIED, Some(mut entry)) => match decode(&entry.body) {
+                Ok(response) => {
+                    entry.fetched = Utc::now();
+                    self.store(&entry);
+                    return Ok(response);
+                }
+                // the validators would only ever get the same copy confirmed again
+                Err(e) => {
+                    warn!("dropping the cached status: {}", e);
+                    self.drop_cache_entry();
+                    self.request(HeaderMap::new(), &mut on_retry).await?
+                }
+            },
+            _ => res,
+        };
+        match res.status() {
+            status if status.is_success() => {
+                let response = decode(res.body())?;
+                self.store(&Entry::from_response(&res));
+                Ok(response)
+            }
+            status => Err(Error::Status(status)),
+        }
+    }
+
+    /// Requests the status with `validators`, retrying as the policy says
+    async fn request(
+        &self,
+        validators: HeaderMap,
+        on_retry: impl FnMut(&Retry<'_>) + Send,
+    ) -> Result<Response<Bytes>> {
+        let url = self.status_url();
+        let attempt = || {
+            let (client, url, validators) = (&self.client, &url, validators.clone());
+            async move {
+                let res = client.get_with_headers(url, validators).await?;
+                match res.status() {
+                    status if status.is_success() || status == StatusCode::NOT_MODIFIED => Ok(res),
+                    status => Err(Error::Status(status)),
+                }
+            }
+        };
+        self.retry.run(attempt, on_retry).await
+    }
+
+    /// The status saved by the last successful fetch and when it was fetched, `None` without a
+    /// cache or before the first fetch
+    pub fn cached(&self) -> Result<Option<(ArchMirrors, DateTime<Utc>)>> {
+        match &self.cache {
+            Some(cache) => Self::cached_in(cache),
+            None => Ok(None),
+        }
+    }
+
+    /// The status a provider caching in `cache` saved, without needing a client
+    pub fn cached_in(cache: &Cache) -> Result<Option<(ArchMirrors, DateTime<Utc>)>> {
+        match cache.load(CACHE_KEY)? {
+            Some(entry) => Ok(Some((decode(&entry.body)?, entry.fetched))),
+            None => Ok(None),
+        }
+    }
+
+    fn cache_entry(&self) -> Option<Entry> {
+        match self.cache.as_ref()?.load(CACHE_KEY) {
+            Ok(entry) => entry,
+            Err(e) => {
+                warn!("ignoring the cached status: {}", e);
+                None
+            }
+        }
+    }
+
+    fn drop_cache_entry(&self) {
+        if let Some(cache) = &self.cache {
+            if let Err(e) = cache.remove(CACHE_KEY) {
+                warn!("{}: {}", cache.dir().display(), e);
+            }
+        }
+    }
+
+    fn store(&self, entry: &Entry) {
+        if let Some(cache) = &self.cache {
+            if let Err(e) = cache.store(CACHE_KEY, entry) {
+                warn!("{}: {}", cache.dir().display(), e);
+            }
+        }
+    }
+
+    fn status_url(&self) -> String {
+        match self.client.base_url() {
+            Some(_) => STATUS_PATH.to_owned(),
+            None => format!("{}{}", ARCHLINUX_URL, STATUS_PATH),
+        }
+    }
+}
+
+impl MirrorProvider for ArchLinux {
+    type Mirrors = ArchMirrors;
+
+    fn name(&self) -> &'static str {
+        "Arch Linux"
+    }
+
+    async fn fetch(&self) -> Result<Self::Mirrors> {
+        self.fetch_reporting(|_| {}).await
+    }
+}
+
+/// Checks the schema version before decoding the rest, so a changed schema is reported as such
+fn decode(body: &[u8]) -> Result<ArchMirrors> {
+    #[derive(Deserialize)]
+    struct Version {
+        version: i64,
+    }
+    let Version { version } = Error::decode(body)?;
+    if version != SCHEMA_VERSION {
+        return Err(Error::UnsupportedVersion {
+            found: version,
+            supported: SCHEMA_VERSION,
+        });
+    }
+    Error::decode(body)
+}
+
+impl From<ArchMirrors> for Vec<Country> {
+    fn from(mirrors: ArchMirrors) -> Self {
+        mirrors
+            .countries
+            .into_iter()
+            .map(|f| Country {
+                name: f.country,
+                code: f.country_code,
+                mirrors: f
+                    .mirrors
+                    .into_iter()
+                    .map(|m| crate::Mirror {
+                        url: m.url,
+                        protocol: m.protocol.to_string(),
+                        last_sync: m.last_sync,
+                        completion_pct: m.completion_pct,
+                        score: m.score,
+                        active: m.active,
+                        ipv4: m.ipv4,
+                        ipv6: m.ipv6,
+                    })
+                    .collect(),
+            })
+            .collect()
+    }
+}
+
+/// Fetches the status from archlinux.org with the default [`Client`]
+pub async fn mirrors() -> Result<ArchMirrors> {
+    ArchLinux::default().fetch().await
+}
 
-const ARCHLINUX_URL: &str = "https://archlinux.org/mirrors/status/json/";
 #[cfg(test)]
 mod tests {
-    use crate::{archlinux::ARCHLINUX_URL, http2_client};
+    use std::{
+        convert::Infallible,
+        net::SocketAddr,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
+    use hyper::{
+        header::{self, IF_NONE_MATCH},
+        service::{make_service_fn, service_fn},
+        Body, Request, Response, Server, StatusCode,
+    };
+
+    use super::{
+        external,
+        internal::{ArchMirrors, Protocol},
+        ArchLinux, ARCHLINUX_URL, STATUS_PATH,
+    };
+    use crate::{Cache, Client, Country, Error, MirrorProvider, RetryPolicy};
+
+    const FIXTURE: &str = include_str!("../../../assets/arch_mirrors.json");
 
     #[tokio::test]
-    async fn archlinux() -> Result<(), Box<dyn std::error::Error>> {
-        let client = http2_client();
-        let response = client.get(ARCHLINUX_URL.parse()?).await?;
+    async fn archlinux() -> crate::Result<()> {
+        let client = Client::default();
+        let response = client
+            .get(&format!("{}{}", ARCHLINUX_URL, STATUS_PATH))
+            .await?;
         assert_eq!(response.status(), hyper::StatusCode::OK);
         Ok(())
     }
-}
 
-pub async fn mirrors() -> Result<ArchMirrors> {
-    let uri = ARCHLINUX_URL.parse()?;
-    let res = http2_client().get(uri).await?;
-    let body = hyper::body::aggregate(res).await?;
-    let response: ArchMirrors = serde_json::from_reader(body.reader())?;
-    Ok(response)
+    const ETAG: &str = "\"fixture\"";
+
+    async fn status(req: Request<Body>) -> Result<Response<Body>, Infallible> {
+        let fresh = req.headers().get(IF_NONE_MATCH).is_some_and(|f| f == ETAG);
+        Ok(match req.uri().path() {
+            "/mirrors/status/json/" if fresh => Response::builder()
+                .status(StatusCode::NOT_MODIFIED)
+                .body(Body::empty())
+                .unwrap(),
+            "/mirrors/status/json/" => Response::builder()
+                .header(header::ETAG, ETAG)
+                .body(Body::from(FIXTURE))
+                .unwrap(),
+            _ => Response::builder()
+                .status(StatusCode::NOT_FOUND)
+                .body(Body::empty())
+                .unwrap(),
+        })
+    }
+
+    fn serve() -> SocketAddr {
+        let make_svc = make_service_fn(|_| async { Ok::<_, Infallible>(service_fn(status)) });
+        let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_svc);
+        let addr = server.local_addr();
+        tokio::spawn(server);
+        addr
+    }
+
+    fn sorted(mut mirrors: ArchMirrors) -> ArchMirrors {
+        mirrors.countries.sort_by(|a, b| a.country.cmp(&b.country));
+        mirrors
+    }
+
+    #[tokio::test]
+    async fn fetches_from_base_url() {
+        let addr = serve();
+        let client = Client::builder()
+            .base_url(format!("http://{}/", addr))
+            .build()
+            .unwrap();
+        let mirrors = ArchLinux::new(client).fetch().await.unwrap();
+        let expected: ArchMirrors = serde_json::from_str(FIXTURE).unwrap();
+        assert_eq!(sorted(mirrors), sorted(expected));
+
+        let client = Client::builder()
+            .base_url(format!("http://{}/missing/", addr))
+            .build()
+            .unwrap();
+        assert!(matches!(
+            ArchLinux::new(client).fetch().await,
+            Err(Error::Status(StatusCode::NOT_FOUND))
+        ));
+    }
+
+    #[tokio::test]
+    async fn caches_and_revalidates_the_status() {
+        let addr = serve();
+        let dir = tempfile::tempdir().unwrap();
+        let client = |path: &str| {
+            Client::builder()
+                .base_url(format!("http://{}/{}", addr, path))
+                .build()
+                .unwrap()
+        };
+        let provider = ArchLinux::new(client("")).with_cache(Cache::new(dir.path()));
+        assert!(provider.cached().unwrap().is_none());
+
+        let fetched = sorted(provider.fetch().await.unwrap());
+        let (cached, first) = provider.cached().unwrap().unwrap();
+        assert_eq!(sorted(cached), fetched);
+        let entry = Cache::new(dir.path())
+            .load(super::CACHE_KEY)
+            .unwrap()
+            .unwrap();
+        assert_eq!(entry.etag.as_deref(), Some(ETAG));
+
+        // answered with 304, served from the cache
+        assert_eq!(sorted(provider.fetch().await.unwrap()), fetched);
+        let (_, second) = provider.cached().unwrap().unwrap();
+        assert!(second >= first);
+
+        // offline, the cached copy is still there
+        let offline = ArchLinux::new(client("missing/")).with_cache(Cache::new(dir.path()));
+        assert!(offline.fetch().await.is_err());
+        assert_eq!(sorted(offline.cached().unwrap().unwrap().0), fetched);
+
+        // a copy that no longer decodes is downloaded again, though the server says it is current
+        let cache = Cache::new(dir.path());
+        let mut corrupt = cache.load(super::CACHE_KEY).unwrap().unwrap();
+        corrupt.body = b"{\"version\": 3}".to_vec();
+        cache.store(super::CACHE_KEY, &corrupt).unwrap();
+        assert!(provider.cached().is_err());
+        assert_eq!(sorted(provider.fetch().await.unwrap()), fetched);
+        assert_eq!(sorted(provider.cached().unwrap().unwrap().0), fetched);
+    }
+
+    #[tokio::test]
+    async fn retries_server_errors() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&calls);
+        let make_svc = make_service_fn(move |_| {
+            let calls = Arc::clone(&counter);
+            async move {
+                Ok::<_, Infallible>(service_fn(move |req| {
+                    let unavailable = calls.fetch_add(1, Ordering::SeqCst) == 0;
+                    async move {
+                        match unavailable {
+                            true => Ok(Response::builder()
+                                .status(StatusCode::SERVICE_UNAVAILABLE)
+                                .body(Body::empty())
+                                .unwrap()),
+                            false => status(req).await,
+                        }
+                    }
+                }))
+            }
+        });
+        let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_svc);
+        let addr = server.local_addr();
+        tokio::spawn(server);
+
+        let client = Client::builder()
+            .base_url(format!("http://{}/", addr))
+            .build()
+            .unwrap();
+        let mut retries = vec![];
+        let provider = ArchLinux::new(client)
+            .with_retry(RetryPolicy::default().backoff(Duration::from_millis(1)));
+        let mirrors = provider
+            .fetch_reporting(|f| retries.push(f.error.to_string()))
+            .await
+            .unwrap();
+        assert!(!mirrors.countries.is_empty());
+        assert_eq!(retries, ["unexpected status 503 Service Unavailable"]);
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn groups_countries_by_code_in_order() {
+        let mirrors: ArchMirrors = serde_json::from_str(FIXTURE).unwrap();
+        let codes: Vec<_> = mirrors
+            .countries
+            .iter()
+            .map(|f| f.country_code.as_str())
+            .collect();
+        assert!(codes.windows(2).all(|f| f[0] < f[1]), "{:?}", codes);
+        let germany = mirrors
+            .countries
+            .iter()
+            .find(|f| f.country_code == "DE")
+            .unwrap();
+        assert_eq!(germany.country, "Germany");
+        assert!(germany.mirrors.len() > 1);
+
+        let raw: serde_json::Value = serde_json::from_str(FIXTURE).unwrap();
+        let total = raw["urls"].as_array().unwrap().len();
+        assert_eq!(
+            mirrors
+                .countries
+                .iter()
+                .map(|f| f.mirrors.len())
+                .sum::<usize>(),
+            total
+        );
+        let again: ArchMirrors = serde_json::from_str(FIXTURE).unwrap();
+        assert_eq!(again, mirrors);
+    }
+
+    #[test]
+    fn round_trips_without_loss() {
+        let mirrors: ArchMirrors = serde_json::from_str(FIXTURE).unwrap();
+        let written = serde_json::to_string(&mirrors).unwrap();
+        let original: serde_json::Value = serde_json::from_str(FIXTURE).unwrap();
+        let round_tripped: serde_json::Value = serde_json::from_str(&written).unwrap();
+        assert_eq!(round_tripped, original);
+        assert_eq!(
+            serde_json::from_str::<ArchMirrors>(&written).unwrap(),
+            mirrors
+        );
+
+        let flat = external::ArchLinuxMirrors::from(mirrors.clone());
+        assert_eq!(flat, serde_json::from_str(FIXTURE).unwrap());
+        assert_eq!(ArchMirrors::try_from(flat).unwrap(), mirrors);
+    }
+
+    #[test]
+    fn normalises_every_mirror() {
+        let mirrors: ArchMirrors = serde_json::from_str(FIXTURE).unwrap();
+        let countries: Vec<Country> = mirrors.clone().into();
+        assert_eq!(countries.len(), mirrors.countries.len());
+        for (country, arch) in countries.iter().zip(mirrors.countries.iter()) {
+            assert_eq!(country.name, arch.country);
+            assert_eq!(country.code, arch.country_code);
+            let urls: Vec<_> = country.mirrors.iter().map(|f| &f.url).collect();
+            let arch_urls: Vec<_> = arch.mirrors.iter().map(|f| &f.url).collect();
+            assert_eq!(urls, arch_urls);
+        }
+    }
+
+    #[test]
+    fn parses_typed_timestamps() {
+        let mirrors: ArchMirrors = serde_json::from_str(FIXTURE).unwrap();
+        assert_eq!(
+            mirrors.last_check.to_rfc3339(),
+            "2022-02-13T12:08:04.349+00:00"
+        );
+        assert_eq!(mirrors.cutoff, chrono::Duration::hours(24));
+        assert_eq!(mirrors.check_frequency, chrono::Duration::seconds(544));
+        let aarnet = mirrors
+            .countries
+            .iter()
+            .flat_map(|f| f.mirrors.iter())
+            .find(|f| f.url == "https://mirror.aarnet.edu.au/pub/archlinux/")
+            .unwrap();
+        assert_eq!(
+            aarnet.last_sync.unwrap().to_rfc3339(),
+            "2022-02-13T11:28:40+00:00"
+        );
+        assert_eq!(aarnet.delay, Some(chrono::Duration::seconds(2010)));
+    }
+
+    #[test]
+    fn parses_protocols() {
+        let mirrors: ArchMirrors = serde_json::from_str(FIXTURE).unwrap();
+        let mirrors: Vec<_> = mirrors
+            .countries
+            .iter()
+            .flat_map(|f| f.mirrors.iter())
+            .collect();
+        for protocol in [Protocol::Http, Protocol::Https, Protocol::Rsync] {
+            assert!(mirrors.iter().any(|f| f.protocol == protocol));
+        }
+        assert!(!mirrors
+            .iter()
+            .any(|f| matches!(f.protocol, Protocol::Other(_))));
+
+        assert_eq!(Protocol::from("HTTPS"), Protocol::Https);
+        assert_eq!(
+            serde_json::from_str::<Protocol>("\"gopher\"").unwrap(),
+            Protocol::Other("gopher".to_owned())
+        );
+        assert_eq!(serde_json::to_string(&Protocol::Ftp).unwrap(), "\"ftp\"");
+    }
+
+    #[test]
+    fn reports_schema_changes() {
+        let json = FIXTURE.replacen("\"version\": 3", "\"version\": 4", 1);
+        assert!(matches!(
+            super::decode(json.as_bytes()),
+            Err(Error::UnsupportedVersion {
+                found: 4,
+                supported: 3
+            })
+        ));
+        let json = FIXTURE.replacen("\"cutoff\": 86400", "\"cutoff\": \"a day\"", 1);
+        match super::decode(json.as_bytes()) {
+            Err(Error::Decode { path, .. }) => assert_eq!(path, "cutoff"),
+            res => panic!("unexpected result {:?}", res.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn skips_malformed_mirrors_and_unknown_fields() {
+        let expected: ArchMirrors = serde_json::from_str(FIXTURE).unwrap();
+        assert_eq!(expected.skipped, 0);
+        let total = |f: &ArchMirrors| f.countries.iter().map(|f| f.mirrors.len()).sum::<usize>();
+
+        let json = FIXTURE
+            .replacen("\"cutoff\": 86400,", "\"cutoff\": 86400, \"mirror_count\": 1,", 1)
+            .replacen(
+                "\"details\": \"https://archlinux.org/mirrors/mir.archlinux.fr/16/\"",
+                "\"details\": \"https://archlinux.org/mirrors/mir.archlinux.fr/16/\", \"sponsor\": \"\"",
+                1,
+            )
+            .replacen("\"urls\": [", "\"urls\": [42, ", 1)
+            .replacen("\"delay\": 2010,", "\"delay\": \"slow\",", 1)
+            .replacen(
+                "\"last_sync\": \"2022-02-13T11:28:40Z\", \"completion_pct\": 1.0, \"delay\": 2009",
+                "\"last_sync\": \"soon\", \"completion_pct\": 1.0, \"delay\": 2009",
+                1,
+            );
+        let mirrors = super::decode(json.as_bytes()).unwrap();
+        assert_eq!(mirrors.skipped, 3);
+        assert_eq!(total(&mirrors), total(&expected) - 2);
+        let urls: Vec<_> = mirrors
+            .countries
+            .iter()
+            .flat_map(|f| f.mirrors.iter().map(|f| f.url.as_str()))
+            .collect();
+        assert!(!urls.contains(&"https://mirror.aarnet.edu.au/pub/archlinux/"));
+        assert!(!urls.contains(&"rsync://mirror.aarnet.edu.au/archlinux/"));
+        assert!(urls.contains(&"http://mir.archlinux.fr/"));
+    }
+
+    #[test]
+    fn reports_invalid_timestamps() {
+        let json = FIXTURE.replacen(
+            "\"last_check\": \"2022-02-13T12:08:04.349Z\"",
+            "\"last_check\": \"yesterday\"",
+            1,
+        );
+        let err = serde_json::from_str::<ArchMirrors>(&json).unwrap_err();
+        assert!(err.to_string().contains("last_check"), "{}", err);
+    }
 }
@@ -0,0 +1,345 @@
+use chrono::{DateTime, Duration, Utc};
+
+use super::internal::{ArchMirrors, Mirror, Protocol, Url};
+
+/// What [`MirrorQuery::mirrors`] are sorted by, mirrors without a value come last
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortBy {
+    /// Lowest (best) score first
+    Score,
+    /// Lowest sync delay first
+    Delay,
+    /// Lowest average download duration first
+    Duration,
+}
+
+/// Filters, sorts and limits the mirrors of an [`ArchMirrors`], see [`ArchMirrors::query`].
+///
+/// Every filter that is set must match, filters set more than once (countries and protocols)
+/// match if any of their values does.
+#[derive(Debug, Clone)]
+pub struct MirrorQuery<'a> {
+    mirrors: &'a ArchMirrors,
+    countries: Vec<String>,
+    country_search: Option<String>,
+    protocols: Vec<Protocol>,
+    active: Option<bool>,
+    isos: Option<bool>,
+    ipv4: Option<bool>,
+    ipv6: Option<bool>,
+    min_completion: Option<f64>,
+    max_delay: Option<Duration>,
+    max_age: Option<Duration>,
+    now: DateTime<Utc>,
+    sort: Option<SortBy>,
+    limit: Option<usize>,
+}
+
+impl ArchMirrors {
+    /// Starts a query matching every mirror
+    pub fn query(&self) -> MirrorQuery<'_> {
+        MirrorQuery {
+            mirrors: self,
+            countries: vec![],
+            country_search: None,
+            protocols: vec![],
+            active: None,
+            isos: None,
+            ipv4: None,
+            ipv6: None,
+            min_completion: None,
+            max_delay: None,
+            max_age: None,
+            now: Utc::now(),
+            sort: None,
+            limit: None,
+        }
+    }
+}
+
+impl<'a> MirrorQuery<'a> {
+    /// Only mirrors in the country with this name or code, ignoring case
+    pub fn country(mut self, name_or_code: impl Into<String>) -> Self {
+        self.countries.push(name_or_code.into());
+        self
+    }
+
+    /// Only mirrors in countries whose name contains `text`, ignoring case
+    pub fn country_search(mut self, text: impl Into<String>) -> Self {
+        self.country_search = Some(text.into().to_lowercase());
+        self
+    }
+
+    /// Only mirrors served over `protocol`
+    pub fn protocol(mut self, protocol: Protocol) -> Self {
+        self.protocols.push(protocol);
+        self
+    }
+
+    /// Only mirrors served over one of `protocols`
+    pub fn protocols(mut self, protocols: impl IntoIterator<Item = Protocol>) -> Self {
+        self.protocols.extend(protocols);
+        self
+    }
+
+    pub fn active(mut self, active: bool) -> Self {
+        self.active = Some(active);
+        self
+    }
+
+    /// Only mirrors that do, or do not, host ISOs
+    pub fn isos(mut self, isos: bool) -> Self {
+        self.isos = Some(isos);
+        self
+    }
+
+    pub fn ipv4(mut self, ipv4: bool) -> Self {
+        self.ipv4 = Some(ipv4);
+        self
+    }
+
+    pub fn ipv6(mut self, ipv6: bool) -> Self {
+        self.ipv6 = Some(ipv6);
+        self
+    }
+
+    /// Only mirrors that are at least `percent` (0 to 100) complete
+    pub fn min_completion(mut self, percent: f64) -> Self {
+        self.min_completion = Some(percent);
+        self
+    }
+
+    /// Only mirrors that were at most `delay` behind when last checked
+    pub fn max_delay(mut self, delay: Duration) -> Self {
+        self.max_delay = Some(delay);
+        self
+    }
+
+    /// Only mirrors synced within `age` of [`MirrorQuery::now`]
+    pub fn max_age(mut self, age: Duration) -> Self {
+        self.max_age = Some(age);
+        self
+    }
+
+    /// The time [`MirrorQuery::max_age`] is measured from, the time the query started by default
+    pub fn now(mut self, now: DateTime<Utc>) -> Self {
+        self.now = now;
+        self
+    }
+
+    pub fn sort(mut self, sort: SortBy) -> Self {
+        self.sort = Some(sort);
+        self
+    }
+
+    /// At most `limit` mirrors, applied after sorting
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    fn matches_country(&self, country: &Url) -> bool {
+        let listed = self.countries.is_empty()
+            || self.countries.iter().any(|f| {
+                f.eq_ignore_ascii_case(&country.country)
+                    || f.eq_ignore_ascii_case(&country.country_code)
+            });
+        let searched = match &self.country_search {
+            Some(text) => country.country.to_lowercase().contains(text),
+            None => true,
+        };
+        listed && searched
+    }
+
+    /// Whether `mirror` passes every filter but the country ones
+    pub fn matches(&self, mirror: &Mirror) -> bool {
+        (self.protocols.is_empty() || self.protocols.contains(&mirror.protocol))
+            && self.active.is_none_or(|f| f == mirror.active)
+            && self.isos.is_none_or(|f| f == mirror.isos)
+            && self.ipv4.is_none_or(|f| f == mirror.ipv4)
+            && self.ipv6.is_none_or(|f| f == mirror.ipv6)
+            && self
+                .min_completion
+                .is_none_or(|f| mirror.completion_pct.unwrap_or_default() * 100.0 >= f)
+            && self
+                .max_delay
+                .is_none_or(|f| mirror.delay.is_some_and(|delay| delay <= f))
+            && self.max_age.is_none_or(|f| {
+                mirror
+                    .last_sync
+                    .is_some_and(|last_sync| self.now - last_sync <= f)
+            })
+    }
+
+    /// Countries that have at least one matching mirror, in their original order
+    pub fn countries(&self) -> Vec<&'a Url> {
+        let mirrors = self.mirrors;
+        mirrors
+            .countries
+            .iter()
+            .filter(|f| self.matches_country(f))
+            .filter(|f| f.mirrors.iter().any(|m| self.matches(m)))
+            .collect()
+    }
+
+    /// Matching mirrors with the country they are listed under, sorted and limited
+    pub fn mirrors(&self) -> Vec<(&'a Url, &'a Mirror)> {
+        let mirrors = self.mirrors;
+        let mut found: Vec<_> = mirrors
+            .countries
+            .iter()
+            .filter(|f| self.matches_country(f))
+            .flat_map(|f| f.mirrors.iter().map(move |m| (f, m)))
+            .filter(|(_, m)| self.matches(m))
+            .collect();
+        match self.sort {
+            Some(SortBy::Score) => found.sort_by(|(_, a), (_, b)| {
+                a.score
+                    .unwrap_or(f64::MAX)
+                    .total_cmp(&b.score.unwrap_or(f64::MAX))
+            }),
+            Some(SortBy::Delay) => {
+                found.sort_by_key(|(_, f)| f.delay.unwrap_or_else(Duration::max_value))
+            }
+            Some(SortBy::Duration) => found.sort_by(|(_, a), (_, b)| {
+                a.duration_avg
+                    .unwrap_or(f64::MAX)
+                    .total_cmp(&b.duration_avg.unwrap_or(f64::MAX))
+            }),
+            None => {}
+        }
+        if let Some(limit) = self.limit {
+            found.truncate(limit);
+        }
+        found
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, TimeZone, Utc};
+
+    use super::SortBy;
+    use crate::archlinux::internal::{ArchMirrors, Protocol};
+
+    fn mirrors() -> ArchMirrors {
+        serde_json::from_str(include_str!("../../../assets/arch_mirrors.json")).unwrap()
+    }
+
+    fn total(mirrors: &ArchMirrors) -> usize {
+        mirrors.countries.iter().map(|f| f.mirrors.len()).sum()
+    }
+
+    #[test]
+    fn matches_everything_without_filters() {
+        let mirrors = mirrors();
+        assert_eq!(mirrors.query().mirrors().len(), total(&mirrors));
+        assert_eq!(mirrors.query().countries().len(), mirrors.countries.len());
+    }
+
+    #[test]
+    fn filters_by_country_name_or_code() {
+        let mirrors = mirrors();
+        let query = mirrors.query().country("germany").country("FR");
+        let countries: Vec<_> = query
+            .countries()
+            .iter()
+            .map(|f| f.country_code.as_str())
+            .collect();
+        assert_eq!(countries.len(), 2);
+        assert!(countries.contains(&"DE") && countries.contains(&"FR"));
+        assert!(query
+            .mirrors()
+            .iter()
+            .all(|(c, _)| c.country == "Germany" || c.country == "France"));
+
+        let searched = mirrors.query().country_search("LAND").countries();
+        assert!(!searched.is_empty());
+        assert!(searched
+            .iter()
+            .all(|f| f.country.to_lowercase().contains("land")));
+    }
+
+    #[test]
+    fn filters_by_protocol_and_flags() {
+        let mirrors = mirrors();
+        let found = mirrors
+            .query()
+            .protocol(Protocol::Https)
+            .active(true)
+            .isos(true)
+            .ipv6(true)
+            .mirrors();
+        assert!(!found.is_empty());
+        assert!(found
+            .iter()
+            .all(|(_, f)| f.protocol == Protocol::Https && f.active && f.isos && f.ipv6));
+
+        let rsync = mirrors.query().protocol(Protocol::Rsync).mirrors().len();
+        let http = mirrors.query().protocol(Protocol::Http).mirrors().len();
+        let both = mirrors
+            .query()
+            .protocols([Protocol::Rsync, Protocol::Http])
+            .mirrors()
+            .len();
+        assert_eq!(both, rsync + http);
+        assert!(mirrors.query().protocol(Protocol::Ftp).mirrors().is_empty());
+        assert!(mirrors
+            .query()
+            .protocol(Protocol::Ftp)
+            .countries()
+            .is_empty());
+
+        let ipv4 = mirrors.query().ipv4(true).mirrors().len();
+        let no_ipv4 = mirrors.query().ipv4(false).mirrors().len();
+        assert_eq!(ipv4 + no_ipv4, total(&mirrors));
+    }
+
+    #[test]
+    fn filters_by_completion_delay_and_age() {
+        let mirrors = mirrors();
+        let found = mirrors.query().min_completion(100.0).mirrors();
+        assert!(!found.is_empty());
+        assert!(found.iter().all(|(_, f)| f.completion_pct == Some(1.0)));
+
+        let found = mirrors.query().max_delay(Duration::minutes(30)).mirrors();
+        assert!(!found.is_empty());
+        assert!(found
+            .iter()
+            .all(|(_, f)| f.delay.unwrap() <= Duration::minutes(30)));
+
+        let now = Utc.ymd(2022, 2, 13).and_hms(12, 8, 4);
+        let found = mirrors
+            .query()
+            .now(now)
+            .max_age(Duration::hours(1))
+            .mirrors();
+        assert!(!found.is_empty());
+        assert!(found.len() < total(&mirrors));
+        assert!(found
+            .iter()
+            .all(|(_, f)| now - f.last_sync.unwrap() <= Duration::hours(1)));
+    }
+
+    #[test]
+    fn sorts_and_limits() {
+        let mirrors = mirrors();
+        let found = mirrors.query().sort(SortBy::Score).mirrors();
+        assert_eq!(found.len(), total(&mirrors));
+        let scored: Vec<_> = found.iter().map_while(|(_, f)| f.score).collect();
+        assert!(scored.windows(2).all(|f| f[0] <= f[1]));
+        assert!(found[scored.len()..].iter().all(|(_, f)| f.score.is_none()));
+
+        let found = mirrors.query().sort(SortBy::Delay).limit(10).mirrors();
+        assert_eq!(found.len(), 10);
+        assert!(found
+            .windows(2)
+            .all(|f| f[0].1.delay.unwrap() <= f[1].1.delay.unwrap()));
+
+        let found = mirrors.query().sort(SortBy::Duration).limit(5).mirrors();
+        assert_eq!(found.len(), 5);
+        assert!(found
+            .windows(2)
+            .all(|f| f[0].1.duration_avg.unwrap() <= f[1].1.duration_avg.unwrap()));
+    }
+}
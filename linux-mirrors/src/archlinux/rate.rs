@@ -0,0 +1,228 @@
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use log::{debug, warn};
+use tokio::sync::Semaphore;
+
+use super::internal::{Mirror, Protocol};
+use crate::{Client, Error, Result};
+
+/// The file every Arch mirror is expected to carry, small enough to download quickly
+pub const DEFAULT_RATE_PATH: &str = "core/os/x86_64/core.db";
+
+/// How mirrors are rated
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RateOptions {
+    /// Path, relative to the mirror's url, of the file to download
+    pub path: String,
+    /// Maximum number of mirrors downloaded from at the same time
+    pub concurrency: usize,
+    /// Time allowed for a single mirror to serve the whole file
+    pub timeout: Duration,
+}
+
+impl Default for RateOptions {
+    fn default() -> Self {
+        Self {
+            path: DEFAULT_RATE_PATH.to_owned(),
+            concurrency: 8,
+            timeout: Duration::from_secs(5),
+        }
+    }
+}
+
+/// A mirror with its measured download speed
+#[derive(Debug, Clone, PartialEq)]
+pub struct RatedMirror {
+    pub mirror: Mirror,
+    /// Bytes downloaded
+    pub bytes: usize,
+    /// Time taken to download `bytes`
+    pub elapsed: Duration,
+}
+
+impl RatedMirror {
+    /// Download speed in bytes per second
+    pub fn throughput(&self) -> f64 {
+        self.bytes as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON)
+    }
+}
+
+/// Whether mirrors served over `protocol` can be rated.
+///
+/// http and https mirrors are probed by downloading [`RateOptions::path`] with the [`Client`],
+/// which does not speak rsync or ftp.
+pub fn can_rate(protocol: &Protocol) -> bool {
+    match protocol {
+        Protocol::Http | Protocol::Https => true,
+        Protocol::Rsync | Protocol::Ftp | Protocol::Other(_) => false,
+    }
+}
+
+/// Downloads [`RateOptions::path`] from every mirror and returns the mirrors that served it,
+/// fastest first.
+///
+/// Mirrors that cannot be rated (see [`can_rate`]), fail or time out are left out.
+pub async fn rate(
+    client: &Client,
+    mirrors: Vec<Mirror>,
+    options: &RateOptions,
+) -> Vec<RatedMirror> {
+    let permits = Arc::new(Semaphore::new(options.concurrency.max(1)));
+    let handles: Vec<_> = mirrors
+        .into_iter()
+        .filter(|mirror| can_rate(&mirror.protocol))
+        .map(|mirror| {
+            let client = client.clone();
+            let permits = Arc::clone(&permits);
+            let path = options.path.clone();
+            let timeout = options.timeout;
+            tokio::spawn(async move {
+                let _permit = permits.acquire_owned().await.ok()?;
+                match tokio::time::timeout(timeout, download(&client, &mirror.url, &path)).await {
+                    Ok(Ok((bytes, elapsed))) => {
+                        debug!("{}: {} bytes in {:?}", mirror.url, bytes, elapsed);
+                        Some(RatedMirror {
+                            mirror,
+                            bytes,
+                            elapsed,
+                        })
+                    }
+                    Ok(Err(e)) => {
+                        warn!("{}: {}", mirror.url, e);
+                        None
+                    }
+                    Err(_) => {
+                        warn!("{}: timed out after {:?}", mirror.url, timeout);
+                        None
+                    }
+                }
+            })
+        })
+        .collect();
+
+    let mut rated = Vec::with_capacity(handles.len());
+    for handle in handles {
+        if let Ok(Some(mirror)) = handle.await {
+            rated.push(mirror);
+        }
+    }
+    rated.sort_by(|a, b| b.throughput().total_cmp(&a.throughput()));
+    rated
+}
+
+async fn download(client: &Client, url: &str, path: &str) -> Result<(usize, Duration)> {
+    let url = format!(
+        "{}/{}",
+        url.trim_end_matches('/'),
+        path.trim_start_matches('/')
+    );
+    let start = Instant::now();
+    let res = client.get(&url).await?;
+    if !res.status().is_success() {
+        return Err(Error::Status(res.status()));
+    }
+    Ok((res.body().len(), start.elapsed()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{convert::Infallible, net::SocketAddr, time::Duration};
+
+    use hyper::{
+        body::Bytes,
+        service::{make_service_fn, service_fn},
+        Body, Request, Response, Server, StatusCode,
+    };
+
+    use super::{rate, RateOptions};
+    use crate::{
+        archlinux::internal::{Mirror, Protocol},
+        Client,
+    };
+
+    const CHUNK: usize = 4096;
+    const CHUNKS: usize = 8;
+
+    /// Serves a fixture file under `/<delay in ms>/`, sleeping between each chunk
+    async fn throttled(req: Request<Body>) -> Result<Response<Body>, Infallible> {
+        let mut segments = req.uri().path().trim_start_matches('/').splitn(2, '/');
+        let delay = segments.next().and_then(|f| f.parse::<u64>().ok());
+        match (delay, segments.next()) {
+            (Some(delay), Some(super::DEFAULT_RATE_PATH)) => {
+                let (mut tx, body) = Body::channel();
+                tokio::spawn(async move {
+                    for _ in 0..CHUNKS {
+                        tokio::time::sleep(Duration::from_millis(delay)).await;
+                        if tx.send_data(Bytes::from(vec![0; CHUNK])).await.is_err() {
+                            break;
+                        }
+                    }
+                });
+                Ok(Response::new(body))
+            }
+            _ => Ok(Response::builder()
+                .status(StatusCode::NOT_FOUND)
+                .body(Body::empty())
+                .unwrap()),
+        }
+    }
+
+    fn serve() -> SocketAddr {
+        let make_svc = make_service_fn(|_| async { Ok::<_, Infallible>(service_fn(throttled)) });
+        let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_svc);
+        let addr = server.local_addr();
+        tokio::spawn(server);
+        addr
+    }
+
+    fn mirror(addr: SocketAddr, path: &str, protocol: Protocol) -> Mirror {
+        Mirror {
+            url: format!("http://{}/{}/", addr, path),
+            protocol,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn ranks_by_throughput() {
+        let addr = serve();
+        let mirrors = vec![
+            mirror(addr, "60", Protocol::Http),
+            mirror(addr, "0", Protocol::Http),
+            mirror(addr, "20", Protocol::Http),
+        ];
+        let rated = rate(&Client::default(), mirrors, &RateOptions::default()).await;
+        let urls: Vec<_> = rated.iter().map(|f| f.mirror.url.as_str()).collect();
+        assert_eq!(
+            urls,
+            vec![
+                format!("http://{}/0/", addr),
+                format!("http://{}/20/", addr),
+                format!("http://{}/60/", addr),
+            ]
+        );
+        assert!(rated.iter().all(|f| f.bytes == CHUNK * CHUNKS));
+    }
+
+    #[tokio::test]
+    async fn drops_failed_slow_and_unsupported_mirrors() {
+        let addr = serve();
+        let mirrors = vec![
+            mirror(addr, "0", Protocol::Http),
+            mirror(addr, "missing", Protocol::Http),
+            mirror(addr, "500", Protocol::Http),
+            mirror(addr, "0", Protocol::Rsync),
+        ];
+        let options = RateOptions {
+            timeout: Duration::from_millis(500),
+            concurrency: 1,
+            ..Default::default()
+        };
+        let rated = rate(&Client::default(), mirrors, &options).await;
+        assert_eq!(rated.len(), 1);
+        assert_eq!(rated[0].mirror.url, format!("http://{}/0/", addr));
+    }
+}
@@ -1,6 +1,8 @@
-mod external {
-    use serde::Deserialize;
-    #[derive(Default, Debug, Clone, PartialEq, Deserialize)]
+/// The status as archlinux.org lists it, one entry per mirror
+pub mod external {
+    use super::internal::Protocol;
+    use serde::{Deserialize, Serialize};
+    #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
     #[serde(rename_all = "camelCase")]
     pub struct ArchLinuxMirrors {
         pub cutoff: i64,
@@ -10,15 +12,24 @@ mod external {
         pub num_checks: i64,
         #[serde(rename = "check_frequency")]
         pub check_frequency: i64,
-        pub urls: Vec<Url>,
+        pub urls: Vec<Entry>,
         pub version: i64,
     }
 
-    #[derive(Default, Debug, Clone, PartialEq, Deserialize)]
+    /// An entry of [`ArchLinuxMirrors::urls`], kept as it was listed when it is not a valid
+    /// [`Url`] so one bad entry does not fail the whole status
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    #[serde(untagged)]
+    pub enum Entry {
+        Url(Url),
+        Malformed(serde_json::Value),
+    }
+
+    #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
     #[serde(rename_all = "camelCase")]
     pub struct Url {
         pub url: String,
-        pub protocol: String,
+        pub protocol: Protocol,
         #[serde(rename = "last_sync")]
         pub last_sync: Option<String>,
         #[serde(rename = "completion_pct")]
@@ -41,26 +52,51 @@ mod external {
 }
 
 pub mod internal {
-    use std::collections::HashMap;
+    use std::{collections::BTreeMap, fmt};
 
     use super::external;
-    use serde::Deserialize;
+    use chrono::{DateTime, Duration, SecondsFormat, TimeZone, Utc};
+    use log::warn;
+    use serde::{Deserialize, Serialize};
 
-    #[derive(Default, Debug, Clone, PartialEq, Deserialize)]
-    #[serde(rename_all = "camelCase")]
-    #[serde(from = "external::ArchLinuxMirrors")]
+    /// The status grouped by country.
+    ///
+    /// It is (de)serialized in the [`external`] form, converting back to it is lossless as long
+    /// as every mirror of a country is listed with the same country name and protocols are in
+    /// lowercase.
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    #[serde(
+        try_from = "external::ArchLinuxMirrors",
+        into = "external::ArchLinuxMirrors"
+    )]
     pub struct ArchMirrors {
-        pub cutoff: i64,
-        #[serde(rename = "last_check")]
-        pub last_check: String,
-        #[serde(rename = "num_checks")]
+        /// Mirrors that have not synced within this long are considered out of date
+        pub cutoff: Duration,
+        pub last_check: DateTime<Utc>,
         pub num_checks: i64,
-        #[serde(rename = "check_frequency")]
-        pub check_frequency: i64,
+        pub check_frequency: Duration,
+        /// Sorted by ISO code, mirrors without a country come first
         pub countries: Vec<Url>,
         pub version: i64,
+        /// How many malformed entries of the status were left out
+        pub skipped: usize,
     }
 
+    impl Default for ArchMirrors {
+        fn default() -> Self {
+            Self {
+                cutoff: Duration::zero(),
+                last_check: Utc.timestamp(0, 0),
+                num_checks: 0,
+                check_frequency: Duration::zero(),
+                countries: vec![],
+                version: 0,
+                skipped: 0,
+            }
+        }
+    }
+
+    /// The mirrors of a country, only (de)serialized as part of [`ArchMirrors`]
     #[derive(Default, Debug, Clone, PartialEq)]
     pub struct Url {
         pub country: String,
@@ -71,10 +107,11 @@ pub mod internal {
     #[derive(Default, Debug, Clone, PartialEq)]
     pub struct Mirror {
         pub url: String,
-        pub protocol: String,
-        pub last_sync: Option<String>,
+        pub protocol: Protocol,
+        pub last_sync: Option<DateTime<Utc>>,
         pub completion_pct: Option<f64>,
-        pub delay: Option<i64>,
+        /// How far behind the main repository the mirror was when last checked
+        pub delay: Option<Duration>,
         pub duration_avg: Option<f64>,
         pub duration_stddev: Option<f64>,
         pub score: Option<f64>,
@@ -83,12 +120,137 @@ pub mod internal {
         pub ipv4: bool,
         pub ipv6: bool,
         pub details: String,
+        pub(crate) position: usize,
+    }
+
+    impl Mirror {
+        /// Where the mirror was in the status's list of urls, used to write it back out in the
+        /// same order
+        pub fn position(&self) -> usize {
+            self.position
+        }
+    }
+
+    /// The protocol a mirror is served over
+    #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+    #[serde(from = "String", into = "String")]
+    pub enum Protocol {
+        Http,
+        Https,
+        Rsync,
+        Ftp,
+        /// A protocol this crate does not know about, as it was listed
+        Other(String),
+    }
+
+    impl Default for Protocol {
+        fn default() -> Self {
+            Self::Other(String::new())
+        }
+    }
+
+    impl Protocol {
+        pub fn as_str(&self) -> &str {
+            match self {
+                Protocol::Http => "http",
+                Protocol::Https => "https",
+                Protocol::Rsync => "rsync",
+                Protocol::Ftp => "ftp",
+                Protocol::Other(protocol) => protocol,
+            }
+        }
+    }
+
+    impl From<&str> for Protocol {
+        /// Known protocols are matched ignoring case
+        fn from(protocol: &str) -> Self {
+            match protocol.to_ascii_lowercase().as_str() {
+                "http" => Protocol::Http,
+                "https" => Protocol::Https,
+                "rsync" => Protocol::Rsync,
+                "ftp" => Protocol::Ftp,
+                _ => Protocol::Other(protocol.to_owned()),
+            }
+        }
     }
 
-    impl From<external::ArchLinuxMirrors> for ArchMirrors {
-        fn from(root: external::ArchLinuxMirrors) -> Self {
-            let mut mirrors = HashMap::<String, Vec<Mirror>>::new();
-            for mirror in &root.urls {
+    impl From<String> for Protocol {
+        fn from(protocol: String) -> Self {
+            Protocol::from(protocol.as_str())
+        }
+    }
+
+    impl From<Protocol> for String {
+        fn from(protocol: Protocol) -> Self {
+            protocol.as_str().to_owned()
+        }
+    }
+
+    impl fmt::Display for Protocol {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "{}", self.as_str())
+        }
+    }
+
+    /// A timestamp in the status response that is not valid RFC 3339
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct InvalidTimestamp {
+        pub field: &'static str,
+        pub value: String,
+        pub source: chrono::ParseError,
+    }
+
+    impl fmt::Display for InvalidTimestamp {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(
+                f,
+                "invalid timestamp in {}: {:?} ({})",
+                self.field, self.value, self.source
+            )
+        }
+    }
+
+    impl std::error::Error for InvalidTimestamp {
+        fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+            Some(&self.source)
+        }
+    }
+
+    fn timestamp(field: &'static str, value: &str) -> Result<DateTime<Utc>, InvalidTimestamp> {
+        DateTime::parse_from_rfc3339(value)
+            .map(|f| f.with_timezone(&Utc))
+            .map_err(|source| InvalidTimestamp {
+                field,
+                value: value.to_owned(),
+                source,
+            })
+    }
+
+    /// The inverse of [`timestamp`], in the format archlinux.org uses
+    fn rfc3339(timestamp: &DateTime<Utc>) -> String {
+        timestamp.to_rfc3339_opts(SecondsFormat::AutoSi, true)
+    }
+
+    impl TryFrom<external::ArchLinuxMirrors> for ArchMirrors {
+        type Error = InvalidTimestamp;
+
+        fn try_from(root: external::ArchLinuxMirrors) -> Result<Self, Self::Error> {
+            // keyed by ISO code, which also keeps the countries sorted by it
+            let mut countries = BTreeMap::<String, Url>::new();
+            let mut skipped = 0;
+            for (position, entry) in root.urls.into_iter().enumerate() {
+                let mirror = match entry {
+                    external::Entry::Url(mirror) => mirror,
+                    external::Entry::Malformed(value) => {
+                        let reason = match serde_json::from_value::<external::Url>(value) {
+                            Err(e) => e.to_string(),
+                            Ok(_) => "not a mirror".to_owned(),
+                        };
+                        warn!("skipping mirror {}: {}", position, reason);
+                        skipped += 1;
+                        continue;
+                    }
+                };
                 let external::Url {
                     url,
                     protocol,
@@ -100,59 +262,99 @@ pub mod internal {
                     score,
                     active,
                     country,
-                    country_code: _,
+                    country_code,
                     isos,
                     ipv4,
                     ipv6,
                     details,
                 } = mirror;
-                mirrors
-                    .entry(country.to_string())
-                    .or_default()
+                let last_sync = match last_sync.as_deref().map(|f| timestamp("last_sync", f)) {
+                    Some(Ok(last_sync)) => Some(last_sync),
+                    Some(Err(e)) => {
+                        warn!("skipping mirror {}: {}", url, e);
+                        skipped += 1;
+                        continue;
+                    }
+                    None => None,
+                };
+                countries
+                    .entry(country_code)
+                    .or_insert_with_key(|code| Url {
+                        country,
+                        country_code: code.clone(),
+                        mirrors: vec![],
+                    })
+                    .mirrors
                     .push(Mirror {
-                        url: url.to_string(),
-                        protocol: protocol.to_string(),
-                        last_sync: last_sync.to_owned(),
-                        completion_pct: *completion_pct,
-                        delay: *delay,
-                        duration_avg: *duration_avg,
-                        duration_stddev: *duration_stddev,
-                        score: *score,
-                        active: *active,
-                        isos: *isos,
-                        ipv4: *ipv4,
-                        ipv6: *ipv6,
-                        details: details.to_string(),
+                        url,
+                        protocol,
+                        last_sync,
+                        completion_pct,
+                        delay: delay.map(Duration::seconds),
+                        duration_avg,
+                        duration_stddev,
+                        score,
+                        active,
+                        isos,
+                        ipv4,
+                        ipv6,
+                        details,
+                        position,
                     })
             }
-            let mirrors = mirrors
-                .into_iter()
-                .map(|(country, mirrors)| {
-                    let mut url = Url {
-                        country: country.clone(),
-                        country_code: String::default(),
-                        mirrors,
-                    };
-
-                    if let Some(loc) = root
-                        .urls
-                        .clone()
-                        .into_iter()
-                        .find(|x| x.country.eq(&country))
-                    {
-                        url.country_code = loc.country_code;
-                    }
-                    url
-                })
-                .collect();
+            let mirrors = countries.into_values().collect();
+            if skipped > 0 {
+                warn!("left out {} malformed mirrors of the status", skipped);
+            }
 
-            Self {
-                cutoff: root.cutoff,
-                last_check: root.last_check,
+            Ok(Self {
+                cutoff: Duration::seconds(root.cutoff),
+                last_check: timestamp("last_check", &root.last_check)?,
                 num_checks: root.num_checks,
-                check_frequency: root.check_frequency,
+                check_frequency: Duration::seconds(root.check_frequency),
                 countries: mirrors,
                 version: root.version,
+                skipped,
+            })
+        }
+    }
+
+    impl From<ArchMirrors> for external::ArchLinuxMirrors {
+        fn from(mirrors: ArchMirrors) -> Self {
+            let mut urls: Vec<_> = mirrors
+                .countries
+                .into_iter()
+                .flat_map(|country| {
+                    country.mirrors.into_iter().map(move |f| {
+                        let url = external::Url {
+                            url: f.url,
+                            protocol: f.protocol,
+                            last_sync: f.last_sync.as_ref().map(rfc3339),
+                            completion_pct: f.completion_pct,
+                            delay: f.delay.map(|f| f.num_seconds()),
+                            duration_avg: f.duration_avg,
+                            duration_stddev: f.duration_stddev,
+                            score: f.score,
+                            active: f.active,
+                            country: country.country.clone(),
+                            country_code: country.country_code.clone(),
+                            isos: f.isos,
+                            ipv4: f.ipv4,
+                            ipv6: f.ipv6,
+                            details: f.details,
+                        };
+                        (f.position, external::Entry::Url(url))
+                    })
+                })
+                .collect();
+            urls.sort_by_key(|(position, _)| *position);
+            Self {
+                cutoff: mirrors.cutoff.num_seconds(),
+                last_check: rfc3339(&mirrors.last_check),
+                num_checks: mirrors.num_checks,
+                check_frequency: mirrors.check_frequency.num_seconds(),
+                urls: urls.into_iter().map(|(_, f)| f).collect(),
+                version: mirrors.version,
             }
         }
     }
@@ -0,0 +1,171 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Utc};
+use hyper::{
+    header::{HeaderValue, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED},
+    HeaderMap, Response,
+};
+use serde::{Deserialize, Serialize};
+
+/// A copy of a response kept on disk, with what is needed to ask the server whether it changed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    /// When the server last confirmed `body` is current
+    pub fetched: DateTime<Utc>,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    pub body: Vec<u8>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Metadata {
+    fetched: DateTime<Utc>,
+    etag: Option<String>,
+    last_modified: Option<String>,
+}
+
+impl Entry {
+    /// Keeps `res`'s body along with its `ETag` and `Last-Modified` headers
+    pub fn from_response(res: &Response<impl AsRef<[u8]>>) -> Self {
+        let header = |name| {
+            res.headers()
+                .get(name)
+                .and_then(|f: &HeaderValue| f.to_str().ok())
+                .map(str::to_owned)
+        };
+        Self {
+            fetched: Utc::now(),
+            etag: header(ETAG),
+            last_modified: header(LAST_MODIFIED),
+            body: res.body().as_ref().to_vec(),
+        }
+    }
+
+    /// `If-None-Match` and `If-Modified-Since` headers for a conditional request
+    pub fn validators(&self) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        let etag = self.etag.as_deref().map(HeaderValue::from_str);
+        if let Some(Ok(etag)) = etag {
+            headers.insert(IF_NONE_MATCH, etag);
+        }
+        let last_modified = self.last_modified.as_deref().map(HeaderValue::from_str);
+        if let Some(Ok(last_modified)) = last_modified {
+            headers.insert(IF_MODIFIED_SINCE, last_modified);
+        }
+        headers
+    }
+}
+
+/// A directory of cached responses, each stored under a key as `<key>.body` and `<key>.meta`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cache {
+    dir: PathBuf,
+}
+
+impl Cache {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// `$XDG_CACHE_HOME/<name>`, or `~/.cache/<name>` when it is not set
+    pub fn xdg(name: &str) -> Option<Self> {
+        let home = match std::env::var_os("XDG_CACHE_HOME") {
+            Some(dir) if Path::new(&dir).is_absolute() => PathBuf::from(dir),
+            _ => PathBuf::from(std::env::var_os("HOME")?).join(".cache"),
+        };
+        Some(Self::new(home.join(name)))
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// The entry stored under `key`, `None` if there is none
+    pub fn load(&self, key: &str) -> io::Result<Option<Entry>> {
+        let meta = match fs::read(self.dir.join(format!("{}.meta", key))) {
+            Ok(meta) => meta,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let meta: Metadata = serde_json::from_slice(&meta)?;
+        let body = fs::read(self.dir.join(format!("{}.body", key)))?;
+        Ok(Some(Entry {
+            fetched: meta.fetched,
+            etag: meta.etag,
+            last_modified: meta.last_modified,
+            body,
+        }))
+    }
+
+    /// Stores `entry` under `key`, replacing what was there.
+    ///
+    /// The metadata is written last so a partially written entry is never loaded with the
+    /// validators of the previous one.
+    pub fn store(&self, key: &str, entry: &Entry) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        let meta = Metadata {
+            fetched: entry.fetched,
+            etag: entry.etag.clone(),
+            last_modified: entry.last_modified.clone(),
+        };
+        let meta = serde_json::to_vec(&meta)?;
+        let _ = fs::remove_file(self.dir.join(format!("{}.meta", key)));
+        self.replace(&format!("{}.body", key), &entry.body)?;
+        self.replace(&format!("{}.meta", key), &meta)
+    }
+
+    /// Forgets the entry stored under `key`, if there is one
+    pub fn remove(&self, key: &str) -> io::Result<()> {
+        for name in [format!("{}.meta", key), format!("{}.body", key)] {
+            match fs::remove_file(self.dir.join(name)) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn replace(&self, name: &str, contents: &[u8]) -> io::Result<()> {
+        let temp = self
+            .dir
+            .join(format!(".{}.{}.tmp", name, std::process::id()));
+        fs::write(&temp, contents)?;
+        fs::rename(&temp, self.dir.join(name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::{
+        header::{ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED},
+        Response,
+    };
+
+    use super::{Cache, Entry};
+
+    #[test]
+    fn stores_and_loads_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = Cache::new(dir.path().join("nested"));
+        assert_eq!(cache.load("status").unwrap(), None);
+
+        let res = Response::builder()
+            .header(ETAG, "\"abc\"")
+            .header(LAST_MODIFIED, "Sun, 13 Feb 2022 12:08:04 GMT")
+            .body(b"{}".to_vec())
+            .unwrap();
+        let entry = Entry::from_response(&res);
+        cache.store("status", &entry).unwrap();
+        assert_eq!(cache.load("status").unwrap(), Some(entry.clone()));
+
+        let validators = entry.validators();
+        assert_eq!(validators[IF_NONE_MATCH], "\"abc\"");
+        assert_eq!(
+            validators[IF_MODIFIED_SINCE],
+            "Sun, 13 Feb 2022 12:08:04 GMT"
+        );
+    }
+}
@@ -0,0 +1,528 @@
+use std::{
+    future::Future,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use hyper::{
+    body::{Bytes, HttpBody},
+    client::HttpConnector,
+    header::{HeaderValue, PROXY_AUTHORIZATION, USER_AGENT},
+    Body, HeaderMap, Request, Response, Uri,
+};
+use hyper_openssl::HttpsConnector;
+use log::warn;
+use openssl::ssl::{SslConnector, SslConnectorBuilder, SslFiletype, SslMethod};
+
+use crate::{proxy::ProxyConnector, Error, Proxy, Result};
+
+pub const DEFAULT_USER_AGENT: &str = concat!("linux-mirrors/", env!("CARGO_PKG_VERSION"));
+pub const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+pub const DEFAULT_READ_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Configures a [`Client`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientBuilder {
+    base_url: Option<String>,
+    connect_timeout: Option<Duration>,
+    read_timeout: Option<Duration>,
+    user_agent: String,
+    http1: bool,
+    proxy: Proxy,
+    ca_bundles: Vec<PathBuf>,
+    client_cert: Option<(PathBuf, PathBuf)>,
+}
+
+impl Default for ClientBuilder {
+    fn default() -> Self {
+        Self {
+            base_url: None,
+            connect_timeout: Some(DEFAULT_CONNECT_TIMEOUT),
+            read_timeout: Some(DEFAULT_READ_TIMEOUT),
+            user_agent: DEFAULT_USER_AGENT.to_owned(),
+            http1: true,
+            proxy: Proxy::from_env(),
+            ca_bundles: vec![],
+            client_cert: None,
+        }
+    }
+}
+
+impl ClientBuilder {
+    /// Relative urls passed to [`Client::get`] are resolved against this url
+    pub fn base_url(mut self, url: impl Into<String>) -> Self {
+        self.base_url = Some(url.into());
+        self
+    }
+
+    /// Time allowed to establish a connection, `None` waits forever
+    pub fn connect_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.connect_timeout = timeout;
+        self
+    }
+
+    /// Time allowed between the request and the response headers, and between body chunks.
+    /// `None` waits forever
+    pub fn read_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.read_timeout = timeout;
+        self
+    }
+
+    pub fn user_agent(mut self, user_agent: impl Into<String>) -> Self {
+        self.user_agent = user_agent.into();
+        self
+    }
+
+    /// Whether HTTP/1.1 may be used when the server does not negotiate HTTP/2, on by default
+    pub fn http1(mut self, enabled: bool) -> Self {
+        self.http1 = enabled;
+        self
+    }
+
+    /// Proxies to send requests through, read from the environment by default
+    pub fn proxy(mut self, proxy: Proxy) -> Self {
+        self.proxy = proxy;
+        self
+    }
+
+    /// PEM file of certificates trusted on top of the system's
+    pub fn ca_bundle(mut self, path: impl Into<PathBuf>) -> Self {
+        self.ca_bundles.push(path.into());
+        self
+    }
+
+    /// PEM certificate chain and private key presented to servers that ask for one
+    pub fn client_cert(mut self, cert: impl Into<PathBuf>, key: impl Into<PathBuf>) -> Self {
+        self.client_cert = Some((cert.into(), key.into()));
+        self
+    }
+
+    fn tls(&self) -> Result<SslConnectorBuilder> {
+        fn config(path: &Path, e: impl std::fmt::Display) -> Error {
+            Error::Config(format!("{}: {}", path.display(), e))
+        }
+        let mut ssl_build = SslConnector::builder(SslMethod::tls())?;
+        for ca in self.ca_bundles.iter() {
+            ssl_build.set_ca_file(ca).map_err(|e| config(ca, e))?;
+        }
+        if let Some((cert, key)) = &self.client_cert {
+            ssl_build
+                .set_certificate_chain_file(cert)
+                .map_err(|e| config(cert, e))?;
+            ssl_build
+                .set_private_key_file(key, SslFiletype::PEM)
+                .map_err(|e| config(key, e))?;
+            ssl_build.check_private_key().map_err(|e| config(key, e))?;
+        }
+        Ok(ssl_build)
+    }
+
+    /// Builds without the proxies when they are unusable, e.g. socks5:// ones read from the
+    /// environment, instead of failing
+    fn build_or_bypass_proxy(self) -> Client {
+        self.clone().build().unwrap_or_else(|e| {
+            warn!("ignoring the configured proxies: {}", e);
+            self.proxy(Proxy::default())
+                .build()
+                .expect("building a client without proxies")
+        })
+    }
+
+    pub fn build(self) -> Result<Client> {
+        let mut ssl_build = self.tls()?;
+        let base_url = match self.base_url {
+            Some(url) => Some(url.parse::<Uri>()?.to_string()),
+            None => None,
+        };
+        let alpn: &[u8] = match self.http1 {
+            true => b"\x02h2\x08http/1.1",
+            false => b"\x02h2",
+        };
+        ssl_build.set_alpn_protos(alpn)?;
+        let mut http = HttpConnector::new();
+        http.enforce_http(false);
+        http.set_connect_timeout(self.connect_timeout);
+        let proxy = ProxyConnector::new(http, self.proxy)?;
+        let https = HttpsConnector::with_connector(proxy.clone(), ssl_build)?;
+        let inner = hyper::Client::builder()
+            .http2_only(!self.http1)
+            .build::<_, Body>(https);
+        Ok(Client {
+            inner,
+            proxy,
+            base_url,
+            read_timeout: self.read_timeout,
+            user_agent: self.user_agent,
+        })
+    }
+}
+
+/// HTTP client shared by the providers and the mirror rating
+#[derive(Debug, Clone)]
+pub struct Client {
+    inner: hyper::Client<HttpsConnector<ProxyConnector>>,
+    proxy: ProxyConnector,
+    base_url: Option<String>,
+    read_timeout: Option<Duration>,
+    user_agent: String,
+}
+
+impl Default for Client {
+    fn default() -> Self {
+        ClientBuilder::default().build_or_bypass_proxy()
+    }
+}
+
+impl Client {
+    pub fn builder() -> ClientBuilder {
+        ClientBuilder::default()
+    }
+
+    pub fn base_url(&self) -> Option<&str> {
+        self.base_url.as_deref()
+    }
+
+    /// Downloads `url`, resolved against the base url when relative, whatever the response
+    /// status is
+    pub async fn get(&self, url: &str) -> Result<Response<Bytes>> {
+        self.get_with_headers(url, HeaderMap::new()).await
+    }
+
+    /// [`Client::get`] with extra request headers, e.g. [`crate::Entry::validators`]
+    pub async fn get_with_headers(&self, url: &str, headers: HeaderMap) -> Result<Response<Bytes>> {
+        let uri = self.resolve(url)?;
+        let mut req = Request::get(&uri)
+            .header(USER_AGENT, &self.user_agent)
+            .body(Body::empty())?;
+        if let Some(authorization) = self.proxy.authorization(&uri) {
+            req.headers_mut()
+                .insert(PROXY_AUTHORIZATION, HeaderValue::from_str(authorization)?);
+        }
+        req.headers_mut().extend(headers);
+        let res = self.within(self.inner.request(req)).await??;
+        let (parts, mut body) = res.into_parts();
+        let mut buf = Vec::new();
+        while let Some(chunk) = self.within(body.data()).await? {
+            buf.extend_from_slice(&chunk?);
+        }
+        Ok(Response::from_parts(parts, Bytes::from(buf)))
+    }
+
+    fn resolve(&self, url: &str) -> Result<Uri> {
+        match &self.base_url {
+            Some(base) if !url.contains("://") => Ok(format!(
+                "{}/{}",
+                base.trim_end_matches('/'),
+                url.trim_start_matches('/')
+            )
+            .parse()?),
+            _ => Ok(url.parse()?),
+        }
+    }
+
+    async fn within<F: Future>(&self, f: F) -> Result<F::Output> {
+        match self.read_timeout {
+            Some(timeout) => tokio::time::timeout(timeout, f)
+                .await
+                .map_err(|_| Error::Timeout(timeout)),
+            None => Ok(f.await),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        convert::Infallible,
+        net::SocketAddr,
+        pin::Pin,
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    use hyper::{
+        header::{PROXY_AUTHORIZATION, USER_AGENT},
+        server::conn::Http,
+        service::{make_service_fn, service_fn},
+        Body, Request, Response, Server,
+    };
+    use openssl::{
+        asn1::Asn1Time,
+        bn::BigNum,
+        hash::MessageDigest,
+        pkey::{PKey, Private},
+        rsa::Rsa,
+        ssl::{SslAcceptor, SslMethod},
+        x509::{
+            extension::{BasicConstraints, SubjectAlternativeName},
+            X509Builder, X509NameBuilder, X509,
+        },
+    };
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{TcpListener, TcpStream},
+    };
+
+    use super::Client;
+    use crate::{Error, Proxy};
+
+    /// Echoes the path and user agent back, `/slow` never answers
+    async fn echo(req: Request<Body>) -> Result<Response<Body>, Infallible> {
+        if req.uri().path() == "/slow" {
+            tokio::time::sleep(Duration::from_secs(60)).await;
+        }
+        let agent = req
+            .headers()
+            .get(USER_AGENT)
+            .and_then(|f| f.to_str().ok())
+            .unwrap_or_default();
+        Ok(Response::new(Body::from(format!(
+            "{} {}",
+            req.uri().path(),
+            agent
+        ))))
+    }
+
+    fn serve() -> SocketAddr {
+        let make_svc = make_service_fn(|_| async { Ok::<_, Infallible>(service_fn(echo)) });
+        let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_svc);
+        let addr = server.local_addr();
+        tokio::spawn(server);
+        addr
+    }
+
+    #[tokio::test]
+    async fn resolves_against_base_url() {
+        let addr = serve();
+        let client = Client::builder()
+            .base_url(format!("http://{}/api/", addr))
+            .user_agent("tests")
+            .build()
+            .unwrap();
+        let res = client.get("status/json/").await.unwrap();
+        assert_eq!(res.body().as_ref(), b"/api/status/json/ tests");
+        let res = client.get(&format!("http://{}/other", addr)).await.unwrap();
+        assert_eq!(res.body().as_ref(), b"/other tests");
+    }
+
+    #[tokio::test]
+    async fn times_out_on_hung_server() {
+        let addr = serve();
+        let client = Client::builder()
+            .base_url(format!("http://{}", addr))
+            .read_timeout(Some(Duration::from_millis(100)))
+            .build()
+            .unwrap();
+        let err = client.get("slow").await.unwrap_err();
+        assert!(matches!(err, Error::Timeout(_)), "{:?}", err);
+        assert!(client.get("fast").await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn rejects_invalid_base_url() {
+        assert!(Client::builder().base_url("not a url").build().is_err());
+    }
+
+    /// Stands in for a proxy: tunnels `CONNECT` requests and answers anything else with its
+    /// request line and `Proxy-Authorization`. Every request line is kept in the returned log
+    async fn proxy() -> (SocketAddr, Arc<Mutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let log = Arc::new(Mutex::new(vec![]));
+        let requests = Arc::clone(&log);
+        tokio::spawn(async move {
+            while let Ok((mut client, _)) = listener.accept().await {
+                let requests = Arc::clone(&requests);
+                tokio::spawn(async move {
+                    let mut head = vec![];
+                    while !head.ends_with(b"\r\n\r\n") {
+                        let mut byte = [0];
+                        if client.read(&mut byte).await.unwrap_or(0) == 0 {
+                            return;
+                        }
+                        head.push(byte[0]);
+                    }
+                    let head = String::from_utf8(head).unwrap();
+                    let line = head.lines().next().unwrap().to_owned();
+                    requests.lock().unwrap().push(line.clone());
+                    match line.strip_prefix("CONNECT ") {
+                        Some(target) => {
+                            let target = target.split_whitespace().next().unwrap();
+                            let mut server = TcpStream::connect(target).await.unwrap();
+                            client
+                                .write_all(b"HTTP/1.1 200 Connection established\r\n\r\n")
+                                .await
+                                .unwrap();
+                            let _ = tokio::io::copy_bidirectional(&mut client, &mut server).await;
+                        }
+                        None => {
+                            let authorization = head
+                                .lines()
+                                .find_map(|f| f.strip_prefix(&format!("{}: ", PROXY_AUTHORIZATION)))
+                                .unwrap_or_default();
+                            let body = format!("{} {}", line, authorization);
+                            let res = format!(
+                                "HTTP/1.1 200 OK\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+                                body.len(),
+                                body
+                            );
+                            client.write_all(res.as_bytes()).await.unwrap();
+                        }
+                    }
+                });
+            }
+        });
+        (addr, log)
+    }
+
+    fn name(cn: &str) -> openssl::x509::X509Name {
+        let mut name = X509NameBuilder::new().unwrap();
+        name.append_entry_by_text("CN", cn).unwrap();
+        name.build()
+    }
+
+    /// A certificate for `subject`, self signed when `issuer` is `None`
+    fn certificate(
+        subject: &str,
+        key: &PKey<Private>,
+        issuer: Option<(&X509, &PKey<Private>)>,
+    ) -> X509 {
+        let mut cert = X509Builder::new().unwrap();
+        cert.set_version(2).unwrap();
+        let serial = BigNum::from_u32(1).unwrap().to_asn1_integer().unwrap();
+        cert.set_serial_number(&serial).unwrap();
+        cert.set_subject_name(&name(subject)).unwrap();
+        cert.set_pubkey(key).unwrap();
+        cert.set_not_before(&Asn1Time::days_from_now(0).unwrap())
+            .unwrap();
+        cert.set_not_after(&Asn1Time::days_from_now(1).unwrap())
+            .unwrap();
+        match issuer {
+            Some((ca, ca_key)) => {
+                cert.set_issuer_name(ca.subject_name()).unwrap();
+                let san = SubjectAlternativeName::new()
+                    .dns(subject)
+                    .build(&cert.x509v3_context(Some(ca), None))
+                    .unwrap();
+                cert.append_extension(san).unwrap();
+                cert.sign(ca_key, MessageDigest::sha256()).unwrap();
+            }
+            None => {
+                cert.set_issuer_name(&name(subject)).unwrap();
+                cert.append_extension(BasicConstraints::new().critical().ca().build().unwrap())
+                    .unwrap();
+                cert.sign(key, MessageDigest::sha256()).unwrap();
+            }
+        }
+        cert.build()
+    }
+
+    /// Serves [`echo`] over TLS for `localhost` with a certificate from a throwaway CA, returns
+    /// the port and the CA
+    async fn serve_tls() -> (u16, X509) {
+        let key = || PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+        let (ca_key, server_key) = (key(), key());
+        let ca = certificate("mirrors test ca", &ca_key, None);
+        let cert = certificate("localhost", &server_key, Some((&ca, &ca_key)));
+        let mut acceptor = SslAcceptor::mozilla_intermediate(SslMethod::tls()).unwrap();
+        acceptor.set_private_key(&server_key).unwrap();
+        acceptor.set_certificate(&cert).unwrap();
+        let acceptor = acceptor.build();
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            while let Ok((tcp, _)) = listener.accept().await {
+                let ssl = openssl::ssl::Ssl::new(acceptor.context()).unwrap();
+                tokio::spawn(async move {
+                    let mut tls = tokio_openssl::SslStream::new(ssl, tcp).unwrap();
+                    if Pin::new(&mut tls).accept().await.is_ok() {
+                        let _ = Http::new().serve_connection(tls, service_fn(echo)).await;
+                    }
+                });
+            }
+        });
+        (port, ca)
+    }
+
+    #[tokio::test]
+    async fn sends_http_requests_to_the_proxy() {
+        let (proxy, log) = proxy().await;
+        let client = Client::builder()
+            .proxy(Proxy::all(format!("http://user:secret@{}", proxy)))
+            .build()
+            .unwrap();
+        let res = client.get("http://mirrors.invalid/status").await.unwrap();
+        assert_eq!(
+            res.body().as_ref(),
+            b"GET http://mirrors.invalid/status HTTP/1.1 Basic dXNlcjpzZWNyZXQ="
+        );
+        assert_eq!(log.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn tunnels_https_through_the_proxy_with_extra_cas() {
+        let (proxy, log) = proxy().await;
+        let (port, ca) = serve_tls().await;
+        let dir = tempfile::tempdir().unwrap();
+        let bundle = dir.path().join("ca.pem");
+        std::fs::write(&bundle, ca.to_pem().unwrap()).unwrap();
+        let url = format!("https://localhost:{}/tunnelled", port);
+
+        let client = Client::builder()
+            .proxy(Proxy::all(format!("http://{}", proxy)))
+            .ca_bundle(&bundle)
+            .user_agent("tests")
+            .build()
+            .unwrap();
+        let res = client.get(&url).await.unwrap();
+        assert_eq!(res.body().as_ref(), b"/tunnelled tests");
+        assert_eq!(
+            log.lock().unwrap().as_slice(),
+            [format!("CONNECT localhost:{} HTTP/1.1", port)]
+        );
+
+        // the throwaway CA is not trusted without the bundle
+        let client = Client::builder()
+            .proxy(Proxy::all(format!("http://{}", proxy)))
+            .build()
+            .unwrap();
+        assert!(client.get(&url).await.is_err());
+
+        // nor is the proxy used for hosts in no_proxy
+        let client = Client::builder()
+            .proxy(Proxy {
+                no_proxy: vec!["localhost".to_owned()],
+                ..Proxy::all(format!("http://{}", proxy))
+            })
+            .ca_bundle(&bundle)
+            .build()
+            .unwrap();
+        assert!(client.get(&url).await.is_ok());
+        assert_eq!(log.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn rejects_missing_certificates() {
+        assert!(Client::builder()
+            .ca_bundle("/nonexistent/ca.pem")
+            .build()
+            .is_err());
+        assert!(Client::builder()
+            .client_cert("/nonexistent/cert.pem", "/nonexistent/key.pem")
+            .build()
+            .is_err());
+        assert!(Client::builder()
+            .proxy(Proxy::all("https://proxy:3128"))
+            .build()
+            .is_err());
+    }
+
+    #[test]
+    fn bypasses_unusable_proxies_by_default() {
+        let builder = Client::builder().proxy(Proxy::all("socks5://proxy:1080"));
+        assert!(builder.clone().build().is_err());
+        let client = builder.build_or_bypass_proxy();
+        assert_eq!(client.proxy.proxy(), &Proxy::default());
+    }
+}
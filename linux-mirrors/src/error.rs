@@ -0,0 +1,171 @@
+use std::{fmt, io, time::Duration};
+
+use hyper::StatusCode;
+
+/// Everything that can go wrong fetching, decoding or caching mirror data
+#[derive(Debug)]
+pub enum Error {
+    /// A url, or a request built from it, is not valid
+    InvalidUrl(hyper::http::Error),
+    /// TLS could not be set up
+    Tls(openssl::error::ErrorStack),
+    /// The client was configured with an unusable proxy, CA bundle or certificate
+    Config(String),
+    /// The server could not be reached or the connection failed, including DNS and TLS
+    /// handshake failures
+    Transport(hyper::Error),
+    /// The server answered with a status other than success
+    Status(StatusCode),
+    /// The response is not in the expected format, `path` is where in the document decoding
+    /// failed
+    Decode {
+        path: String,
+        source: serde_json::Error,
+    },
+    /// No response within the client's read timeout
+    Timeout(Duration),
+    /// The response is in a schema version this crate does not understand
+    UnsupportedVersion { found: i64, supported: i64 },
+    /// The cache could not be read or written
+    Io(io::Error),
+}
+
+impl Error {
+    /// Whether trying again later might succeed
+    pub fn is_transient(&self) -> bool {
+        match self {
+            Error::Transport(_) | Error::Timeout(_) => true,
+            Error::Status(status) => {
+                status.is_server_error() || *status == StatusCode::TOO_MANY_REQUESTS
+            }
+            Error::InvalidUrl(_)
+            | Error::Tls(_)
+            | Error::Config(_)
+            | Error::Decode { .. }
+            | Error::UnsupportedVersion { .. }
+            | Error::Io(_) => false,
+        }
+    }
+
+    /// Decodes `T` from `json`, reporting where decoding failed
+    pub(crate) fn decode<'de, T: serde::Deserialize<'de>>(json: &'de [u8]) -> Result<T, Error> {
+        let de = &mut serde_json::Deserializer::from_slice(json);
+        serde_path_to_error::deserialize(de).map_err(|e| Error::Decode {
+            path: e.path().to_string(),
+            source: e.into_inner(),
+        })
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::InvalidUrl(e) => write!(f, "invalid url: {}", e),
+            Error::Tls(e) => write!(f, "could not set up tls: {}", e),
+            Error::Config(e) => write!(f, "{}", e),
+            Error::Transport(e) => write!(f, "request failed: {}", e),
+            Error::Status(status) => write!(f, "unexpected status {}", status),
+            Error::Decode { path, source } => write!(f, "invalid response at {}: {}", path, source),
+            Error::Timeout(timeout) => write!(f, "no response within {:?}", timeout),
+            Error::UnsupportedVersion { found, supported } => write!(
+                f,
+                "unsupported schema version {}, expected {}",
+                found, supported
+            ),
+            Error::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::InvalidUrl(e) => Some(e),
+            Error::Tls(e) => Some(e),
+            Error::Transport(e) => Some(e),
+            Error::Decode { source, .. } => Some(source),
+            Error::Io(e) => Some(e),
+            Error::Config(_)
+            | Error::Status(_)
+            | Error::Timeout(_)
+            | Error::UnsupportedVersion { .. } => None,
+        }
+    }
+}
+
+impl From<hyper::http::Error> for Error {
+    fn from(e: hyper::http::Error) -> Self {
+        Error::InvalidUrl(e)
+    }
+}
+
+impl From<hyper::http::uri::InvalidUri> for Error {
+    fn from(e: hyper::http::uri::InvalidUri) -> Self {
+        Error::InvalidUrl(e.into())
+    }
+}
+
+impl From<hyper::header::InvalidHeaderValue> for Error {
+    fn from(e: hyper::header::InvalidHeaderValue) -> Self {
+        Error::InvalidUrl(e.into())
+    }
+}
+
+impl From<openssl::error::ErrorStack> for Error {
+    fn from(e: openssl::error::ErrorStack) -> Self {
+        Error::Tls(e)
+    }
+}
+
+impl From<hyper::Error> for Error {
+    fn from(e: hyper::Error) -> Self {
+        Error::Transport(e)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::StatusCode;
+
+    use super::Error;
+
+    #[derive(Debug, serde::Deserialize)]
+    struct Outer {
+        #[allow(dead_code)]
+        inner: Vec<Inner>,
+    }
+
+    #[derive(Debug, serde::Deserialize)]
+    struct Inner {
+        #[allow(dead_code)]
+        count: i64,
+    }
+
+    #[test]
+    fn reports_the_path_of_decode_errors() {
+        let err =
+            Error::decode::<Outer>(br#"{"inner": [{"count": 1}, {"count": "two"}]}"#).unwrap_err();
+        match err {
+            Error::Decode { path, .. } => assert_eq!(path, "inner[1].count"),
+            e => panic!("unexpected error {}", e),
+        }
+    }
+
+    #[test]
+    fn only_network_failures_are_transient() {
+        assert!(Error::Status(StatusCode::BAD_GATEWAY).is_transient());
+        assert!(Error::Status(StatusCode::TOO_MANY_REQUESTS).is_transient());
+        assert!(!Error::Status(StatusCode::NOT_FOUND).is_transient());
+        assert!(!Error::UnsupportedVersion {
+            found: 4,
+            supported: 3
+        }
+        .is_transient());
+    }
+}
@@ -0,0 +1,49 @@
+use std::future::Future;
+
+use chrono::{DateTime, Utc};
+
+use crate::Result;
+
+/// A mirror, independent of the distribution it serves
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct Mirror {
+    pub url: String,
+    pub protocol: String,
+    pub last_sync: Option<DateTime<Utc>>,
+    /// Between 0 and 1
+    pub completion_pct: Option<f64>,
+    /// Lower is better, only comparable between mirrors of the same provider
+    pub score: Option<f64>,
+    pub active: bool,
+    pub ipv4: bool,
+    pub ipv6: bool,
+}
+
+/// The mirrors hosted in a country
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct Country {
+    pub name: String,
+    /// ISO 3166-1 alpha-2 code, empty for mirrors that are not tied to a country
+    pub code: String,
+    pub mirrors: Vec<Mirror>,
+}
+
+/// A source of mirrors for a distribution.
+///
+/// Each provider lives behind a cargo feature named after its distribution. `fetch` returns the
+/// provider's own representation, which keeps everything the distribution reports, while
+/// [`MirrorProvider::countries`] gives the normalised form shared by every provider.
+pub trait MirrorProvider: Send + Sync {
+    type Mirrors: Into<Vec<Country>> + Send;
+
+    /// Human readable name of the distribution
+    fn name(&self) -> &'static str;
+
+    /// Downloads the current mirror status
+    fn fetch(&self) -> impl Future<Output = Result<Self::Mirrors>> + Send;
+
+    /// Downloads the current mirror status in the normalised form
+    fn countries(&self) -> impl Future<Output = Result<Vec<Country>>> + Send {
+        async move { Ok(self.fetch().await?.into()) }
+    }
+}
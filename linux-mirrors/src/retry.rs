@@ -0,0 +1,334 @@
+use std::{fmt, future::Future, time::Duration};
+
+use hyper::StatusCode;
+use serde::Deserialize;
+
+use crate::{Error, Result};
+
+pub const DEFAULT_MAX_ATTEMPTS: u32 = 3;
+pub const DEFAULT_BACKOFF: Duration = Duration::from_millis(500);
+pub const DEFAULT_MAX_BACKOFF: Duration = Duration::from_secs(8);
+
+/// How failed requests are tried again.
+///
+/// The wait before the nth retry is `backoff * 2^(n - 1)`, capped at `max_backoff`, of which up
+/// to `jitter` (between 0 and 1) is taken off at random so clients do not retry in lockstep.
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    backoff: Duration,
+    max_backoff: Duration,
+    jitter: f64,
+    retry: Retryable,
+}
+
+/// A kind of failure that [`RetryPolicy::retry_on`] can try again after
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RetryOn {
+    /// See [`Error::Transport`]
+    Transport,
+    /// See [`Error::Timeout`]
+    Timeout,
+    /// Any 5xx status
+    #[serde(rename = "5xx")]
+    ServerError,
+    /// A 429 status
+    #[serde(rename = "429")]
+    TooManyRequests,
+}
+
+impl RetryOn {
+    pub fn matches(self, error: &Error) -> bool {
+        match (self, error) {
+            (RetryOn::Transport, Error::Transport(_)) | (RetryOn::Timeout, Error::Timeout(_)) => {
+                true
+            }
+            (RetryOn::ServerError, Error::Status(status)) => status.is_server_error(),
+            (RetryOn::TooManyRequests, Error::Status(status)) => {
+                *status == StatusCode::TOO_MANY_REQUESTS
+            }
+            _ => false,
+        }
+    }
+
+    fn bit(self) -> u8 {
+        1 << self as u8
+    }
+}
+
+/// Which errors are retried, kept `Copy` so policies can be handed around freely
+#[derive(Debug, Clone, Copy)]
+enum Retryable {
+    If(fn(&Error) -> bool),
+    /// A bit per [`RetryOn`]
+    On(u8),
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: DEFAULT_MAX_ATTEMPTS,
+            backoff: DEFAULT_BACKOFF,
+            max_backoff: DEFAULT_MAX_BACKOFF,
+            jitter: 0.5,
+            retry: Retryable::If(Error::is_transient),
+        }
+    }
+}
+
+/// A retry about to happen, see [`RetryPolicy::run`]
+#[derive(Debug)]
+pub struct Retry<'a> {
+    /// The attempt that is about to start, 2 for the first retry
+    pub attempt: u32,
+    pub max_attempts: u32,
+    /// How long until it starts
+    pub delay: Duration,
+    /// Why the previous attempt failed
+    pub error: &'a Error,
+}
+
+impl fmt::Display for Retry<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}, attempt {} of {} in {:.1}s",
+            self.error,
+            self.attempt,
+            self.max_attempts,
+            self.delay.as_secs_f64()
+        )
+    }
+}
+
+impl RetryPolicy {
+    /// Tries once and gives up
+    pub fn never() -> Self {
+        Self::default().max_attempts(1)
+    }
+
+    /// Attempts in total, including the first one, at least 1
+    pub fn max_attempts(mut self, attempts: u32) -> Self {
+        self.max_attempts = attempts.max(1);
+        self
+    }
+
+    /// Wait before the first retry, doubled for every one after it
+    pub fn backoff(mut self, backoff: Duration) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    pub fn max_backoff(mut self, max_backoff: Duration) -> Self {
+        self.max_backoff = max_backoff;
+        self
+    }
+
+    /// Fraction of every wait taken off at random, clamped between 0 and 1
+    pub fn jitter(mut self, jitter: f64) -> Self {
+        self.jitter = jitter.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Which errors are retried, [`Error::is_transient`] ones by default
+    pub fn retry_if(mut self, retry_if: fn(&Error) -> bool) -> Self {
+        self.retry = Retryable::If(retry_if);
+        self
+    }
+
+    /// Retries only these kinds of failures, replacing [`RetryPolicy::retry_if`]
+    pub fn retry_on(mut self, failures: impl IntoIterator<Item = RetryOn>) -> Self {
+        self.retry = Retryable::On(failures.into_iter().fold(0, |mask, f| mask | f.bit()));
+        self
+    }
+
+    /// Whether a request that failed with `error` is tried again, if attempts are left
+    pub fn retries(&self, error: &Error) -> bool {
+        match self.retry {
+            Retryable::If(retry_if) => retry_if(error),
+            Retryable::On(mask) => [
+                RetryOn::Transport,
+                RetryOn::Timeout,
+                RetryOn::ServerError,
+                RetryOn::TooManyRequests,
+            ]
+            .into_iter()
+            .any(|f| mask & f.bit() != 0 && f.matches(error)),
+        }
+    }
+
+    /// The wait before the `retry`th retry, without jitter
+    pub fn backoff_for(&self, retry: u32) -> Duration {
+        let factor = 2_u32.saturating_pow(retry.saturating_sub(1));
+        self.backoff
+            .checked_mul(factor)
+            .unwrap_or(self.max_backoff)
+            .min(self.max_backoff)
+    }
+
+    fn delay_for(&self, retry: u32) -> Duration {
+        let mut random = [0; 4];
+        let fraction = match openssl::rand::rand_bytes(&mut random) {
+            Ok(()) => f64::from(u32::from_ne_bytes(random)) / f64::from(u32::MAX),
+            Err(_) => 0.5,
+        };
+        self.backoff_for(retry)
+            .mul_f64(1.0 - self.jitter * fraction)
+    }
+
+    /// Runs `attempt` until it succeeds, fails with an error that is not retried or runs out of
+    /// attempts. `on_retry` is called before waiting for each retry
+    pub async fn run<T, F, Fut>(
+        &self,
+        mut attempt: F,
+        mut on_retry: impl FnMut(&Retry<'_>),
+    ) -> Result<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let mut attempts = 1;
+        loop {
+            match attempt().await {
+                Err(error) if attempts < self.max_attempts && self.retries(&error) => {
+                    let delay = self.delay_for(attempts);
+                    attempts += 1;
+                    on_retry(&Retry {
+                        attempt: attempts,
+                        max_attempts: self.max_attempts,
+                        delay,
+                        error: &error,
+                    });
+                    tokio::time::sleep(delay).await;
+                }
+                res => return res,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use hyper::StatusCode;
+
+    use super::{RetryOn, RetryPolicy};
+    use crate::Error;
+
+    fn policy() -> RetryPolicy {
+        RetryPolicy::default().backoff(Duration::from_millis(1))
+    }
+
+    #[test]
+    fn backs_off_exponentially_up_to_the_maximum() {
+        let policy = RetryPolicy::default()
+            .backoff(Duration::from_secs(1))
+            .max_backoff(Duration::from_secs(5));
+        let backoff: Vec<_> = (1..=5).map(|f| policy.backoff_for(f).as_secs()).collect();
+        assert_eq!(backoff, [1, 2, 4, 5, 5]);
+        assert_eq!(policy.backoff_for(u32::MAX), Duration::from_secs(5));
+
+        let jittered = policy.jitter(0.5);
+        for _ in 0..100 {
+            let delay = jittered.delay_for(2);
+            assert!(delay >= Duration::from_secs(1) && delay <= Duration::from_secs(2));
+        }
+        assert_eq!(policy.jitter(0.0).delay_for(3), Duration::from_secs(4));
+    }
+
+    #[tokio::test]
+    async fn retries_transient_errors_until_success() {
+        let mut calls = 0;
+        let mut retries = vec![];
+        let res = policy()
+            .run(
+                || {
+                    calls += 1;
+                    let fail = calls < 3;
+                    async move {
+                        match fail {
+                            true => Err(Error::Status(StatusCode::BAD_GATEWAY)),
+                            false => Ok(calls),
+                        }
+                    }
+                },
+                |f| retries.push((f.attempt, f.max_attempts)),
+            )
+            .await;
+        assert_eq!(res.unwrap(), 3);
+        assert_eq!(retries, [(2, 3), (3, 3)]);
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_the_last_attempt() {
+        let mut calls = 0;
+        let res: crate::Result<()> = policy()
+            .max_attempts(2)
+            .run(
+                || {
+                    calls += 1;
+                    async { Err(Error::Timeout(Duration::from_secs(1))) }
+                },
+                |_| {},
+            )
+            .await;
+        assert!(matches!(res, Err(Error::Timeout(_))));
+        assert_eq!(calls, 2);
+    }
+
+    #[tokio::test]
+    async fn does_not_retry_permanent_errors() {
+        let mut calls = 0;
+        let res: crate::Result<()> = policy()
+            .run(
+                || {
+                    calls += 1;
+                    async { Err(Error::Status(StatusCode::NOT_FOUND)) }
+                },
+                |_| panic!("retried a permanent error"),
+            )
+            .await;
+        assert!(matches!(res, Err(Error::Status(StatusCode::NOT_FOUND))));
+        assert_eq!(calls, 1);
+
+        let mut calls = 0;
+        let _ = policy()
+            .retry_if(|_| true)
+            .run(
+                || {
+                    calls += 1;
+                    async { Err::<(), _>(Error::Status(StatusCode::NOT_FOUND)) }
+                },
+                |_| {},
+            )
+            .await;
+        assert_eq!(calls, 3);
+    }
+
+    #[test]
+    fn retries_only_the_chosen_failures() {
+        let policy = RetryPolicy::default().retry_on([RetryOn::ServerError, RetryOn::Timeout]);
+        assert!(policy.retries(&Error::Status(StatusCode::BAD_GATEWAY)));
+        assert!(policy.retries(&Error::Timeout(Duration::from_secs(1))));
+        assert!(!policy.retries(&Error::Status(StatusCode::TOO_MANY_REQUESTS)));
+        assert!(!policy.retries(&Error::Status(StatusCode::NOT_FOUND)));
+
+        let never = RetryPolicy::default().retry_on([]);
+        assert!(!never.retries(&Error::Status(StatusCode::BAD_GATEWAY)));
+        assert!(RetryPolicy::default().retries(&Error::Status(StatusCode::TOO_MANY_REQUESTS)));
+
+        let failures: Vec<RetryOn> =
+            serde_json::from_str(r#"["transport", "timeout", "5xx", "429"]"#).unwrap();
+        assert_eq!(
+            failures,
+            [
+                RetryOn::Transport,
+                RetryOn::Timeout,
+                RetryOn::ServerError,
+                RetryOn::TooManyRequests
+            ]
+        );
+    }
+}
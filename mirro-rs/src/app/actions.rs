@@ -20,13 +20,15 @@ pub enum Action {
 impl Action {
     /// All available actions
     pub fn iterator() -> Iter<'static, Action> {
-        static ACTIONS: [Action; 9] = [
+        static ACTIONS: [Action; 11] = [
             Action::Quit,
             Action::Sleep,
             Action::Focus(Widgets::CountryFilter),
             Action::Focus(Widgets::Protocols),
             Action::Focus(Widgets::Mirrors),
             Action::Focus(Widgets::SelectedCountries),
+            Action::Focus(Widgets::CountryMirrors),
+            Action::Focus(Widgets::MirrorDetails),
             Action::Action,
             Action::SimpleExport,
             Action::RateExport,
@@ -43,6 +45,8 @@ impl Action {
             Action::Focus(Widgets::Protocols) => &[Key::Ctrl('p')],
             Action::Focus(Widgets::Mirrors) => &[Key::Ctrl('a')],
             Action::Focus(Widgets::SelectedCountries) => &[Key::Ctrl('o')],
+            Action::Focus(Widgets::CountryMirrors) => &[Key::Ctrl('s')],
+            Action::Focus(Widgets::MirrorDetails) => &[Key::Ctrl('d')],
             Action::Action => &[
                 Key::Char('a'),
                 Key::Char('b'),
@@ -70,10 +74,38 @@ impl Action {
                 Key::Char('x'),
                 Key::Char('y'),
                 Key::Char('z'),
+                Key::Char('A'),
+                Key::Char('B'),
+                Key::Char('C'),
+                Key::Char('D'),
+                Key::Char('E'),
+                Key::Char('F'),
+                Key::Char('G'),
+                Key::Char('H'),
+                Key::Char('I'),
+                Key::Char('J'),
+                Key::Char('K'),
+                Key::Char('L'),
+                Key::Char('M'),
+                Key::Char('N'),
+                Key::Char('O'),
+                Key::Char('P'),
+                Key::Char('Q'),
+                Key::Char('R'),
+                Key::Char('S'),
+                Key::Char('T'),
+                Key::Char('U'),
+                Key::Char('V'),
+                Key::Char('W'),
+                Key::Char('X'),
+                Key::Char('Y'),
+                Key::Char('Z'),
                 Key::Char(' '),
                 Key::Up,
                 Key::Enter,
                 Key::Down,
+                Key::Left,
+                Key::Right,
                 Key::Backspace,
                 Key::Esc,
             ],
@@ -94,6 +126,8 @@ impl Display for Action {
             Action::Focus(Widgets::Protocols) => "Focus Protocols",
             Action::Focus(Widgets::Mirrors) => "Focus Mirrors",
             Action::Focus(Widgets::SelectedCountries) => "Selected Mirrors",
+            Action::Focus(Widgets::CountryMirrors) => "Focus Country Mirrors",
+            Action::Focus(Widgets::MirrorDetails) => "Focus Mirror Details",
             Action::Action => "Action",
             Action::SimpleExport => "Export without rate",
             Action::RateExport => "Export with rate",
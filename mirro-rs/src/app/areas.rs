@@ -0,0 +1,174 @@
+use tui::layout::Rect;
+
+use super::{state::Widgets, ScrollableTables};
+
+/// Where the widgets were last drawn, so that mouse events can find what is under the pointer
+#[derive(Debug, Clone, Default)]
+pub struct Areas {
+    /// Widgets in the order they were drawn, later ones are on top
+    widgets: Vec<(Widgets, Rect)>,
+    /// The protocol checkboxes, in the order of [`super::state::Protocols::checkboxes`]
+    protocols: Vec<Rect>,
+    all_mirrors: TableView,
+    saved_mirrors: TableView,
+    focused_country: TableView,
+}
+
+impl Areas {
+    /// Forgets the widgets before they are drawn again, tables keep their scroll offset
+    pub fn clear(&mut self) {
+        self.widgets.clear();
+        self.protocols.clear();
+    }
+
+    pub fn widget(&mut self, widget: Widgets, area: Rect) {
+        self.widgets.push((widget, area));
+    }
+
+    pub fn protocol(&mut self, area: Rect) {
+        self.protocols.push(area);
+    }
+
+    /// The topmost widget at `column` and `row`
+    pub fn widget_at(&self, column: u16, row: u16) -> Option<Widgets> {
+        self.widgets
+            .iter()
+            .rev()
+            .find(|(_, area)| contains(area, column, row))
+            .map(|(widget, _)| *widget)
+    }
+
+    /// The index of the protocol checkbox at `column` and `row`
+    pub fn protocol_at(&self, column: u16, row: u16) -> Option<usize> {
+        self.protocols.iter().position(|f| contains(f, column, row))
+    }
+
+    pub fn table(&mut self, table: ScrollableTables) -> &mut TableView {
+        match table {
+            ScrollableTables::AllMirrors => &mut self.all_mirrors,
+            ScrollableTables::SavedMirrors => &mut self.saved_mirrors,
+            ScrollableTables::FocusedCountry => &mut self.focused_country,
+        }
+    }
+}
+
+/// The rows of a bordered table with rows one line high.
+///
+/// tui does not expose which row is at the top of a table, so it is worked out the same way
+/// tui does: the top row only moves when the selected row would be out of view.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TableView {
+    /// Where the rows are drawn, inside the borders and below the header
+    rows: Rect,
+    /// The index of the top row
+    offset: usize,
+}
+
+impl TableView {
+    /// Records a table drawn in `area` with `len` rows and `selected` selected
+    pub fn drawn(&mut self, area: Rect, header: bool, selected: Option<usize>, len: usize) {
+        let header = u16::from(header);
+        self.rows = Rect {
+            x: area.x.saturating_add(1),
+            y: area.y.saturating_add(1 + header),
+            width: area.width.saturating_sub(2),
+            height: area.height.saturating_sub(2 + header),
+        };
+        let selected = match selected {
+            Some(selected) => selected,
+            None => {
+                self.offset = 0;
+                return;
+            }
+        };
+        if len == 0 {
+            return;
+        }
+        let selected = selected.min(len - 1);
+        let offset = self.offset.min(len - 1);
+        let height = usize::from(self.rows.height).max(1);
+        self.offset = if selected < offset {
+            selected
+        } else if selected >= offset + height {
+            selected + 1 - height
+        } else {
+            offset
+        };
+    }
+
+    /// The index of the row at `row`, if one is drawn there
+    pub fn row_at(&self, column: u16, row: u16, len: usize) -> Option<usize> {
+        if !contains(&self.rows, column, row) {
+            return None;
+        }
+        let index = self.offset + usize::from(row - self.rows.y);
+        (index < len).then_some(index)
+    }
+}
+
+fn contains(area: &Rect, column: u16, row: u16) -> bool {
+    column >= area.x
+        && column < area.x.saturating_add(area.width)
+        && row >= area.y
+        && row < area.y.saturating_add(area.height)
+}
+
+#[cfg(test)]
+mod tests {
+    use tui::{
+        backend::TestBackend,
+        layout::Rect,
+        widgets::{Block, Borders, Row, Table, TableState},
+        Terminal,
+    };
+
+    use super::{Areas, TableView};
+    use crate::app::state::Widgets;
+
+    #[test]
+    fn should_find_the_topmost_widget() {
+        let mut areas = Areas::default();
+        areas.widget(Widgets::Mirrors, Rect::new(0, 0, 20, 10));
+        areas.widget(Widgets::MirrorDetails, Rect::new(5, 5, 5, 5));
+        assert_eq!(areas.widget_at(0, 0), Some(Widgets::Mirrors));
+        assert_eq!(areas.widget_at(6, 6), Some(Widgets::MirrorDetails));
+        assert_eq!(areas.widget_at(10, 9), Some(Widgets::Mirrors));
+        assert_eq!(areas.widget_at(20, 0), None);
+        areas.clear();
+        assert_eq!(areas.widget_at(0, 0), None);
+    }
+
+    #[test]
+    fn should_scroll_like_tui() {
+        let len = 30;
+        let area = Rect::new(0, 0, 10, 10);
+        let mut terminal = Terminal::new(TestBackend::new(10, 10)).unwrap();
+        let mut state = TableState::default();
+        let mut view = TableView::default();
+        for selected in [0, 3, 12, 29, 20, 13, 5, 6, 29, 0, 17] {
+            state.select(Some(selected));
+            terminal
+                .draw(|f| {
+                    let rows = (0..len).map(|f| Row::new(vec![f.to_string()]));
+                    let table = Table::new(rows)
+                        .header(Row::new(vec!["#"]))
+                        .block(Block::default().borders(Borders::ALL))
+                        .widths(&[tui::layout::Constraint::Min(5)]);
+                    f.render_stateful_widget(table, area, &mut state);
+                })
+                .unwrap();
+            view.drawn(area, true, Some(selected), len);
+
+            // whichever row tui drew at the top is the one the view expects there
+            let buffer = terminal.backend().buffer();
+            let top: String = (1..9)
+                .map(|x| buffer.get(x, 2).symbol.clone())
+                .collect::<String>()
+                .trim()
+                .to_owned();
+            assert_eq!(view.row_at(1, 2, len), top.parse().ok(), "{}", selected);
+            assert_eq!(view.row_at(1, 8, len), Some(view.offset + 6));
+            assert_eq!(view.row_at(1, 9, len), None);
+        }
+    }
+}
@@ -1,10 +1,199 @@
-use serde::Deserialize;
+use std::{fs, io, io::Write, path::PathBuf, time::Duration};
+
+use linux_mirrors::{Client, Error, Proxy, RetryOn, RetryPolicy};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use super::{
+    export,
+    state::{SortDirection, SortKey},
+};
+
+pub const DEFAULT_EXPORT_PATH: &str = "/etc/pacman.d/mirrorlist";
+pub const DEFAULT_EXPORT_BACKUPS: usize = 3;
 
 #[derive(Default, Debug, Clone, PartialEq, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct MirrorsConfig {
     pub colours: Option<Colours>,
     pub icons: Option<Icons>,
+    pub export: Option<Export>,
+    pub network: Option<Network>,
+    pub sort: Option<Sort>,
+    /// Where the sort chosen in the app is remembered, it is used instead of the `[sort]`
+    /// section once there
+    #[serde(skip)]
+    pub state: Option<PathBuf>,
+}
+
+impl MirrorsConfig {
+    /// Where the mirrorlist is written
+    pub fn export_path(&self) -> PathBuf {
+        match &self.export {
+            Some(Export {
+                path: Some(path), ..
+            }) => PathBuf::from(path),
+            _ => PathBuf::from(DEFAULT_EXPORT_PATH),
+        }
+    }
+
+    /// How many previous mirrorlists are kept when exporting
+    pub fn export_backups(&self) -> usize {
+        match &self.export {
+            Some(Export {
+                backups: Some(backups),
+                ..
+            }) => *backups,
+            _ => DEFAULT_EXPORT_BACKUPS,
+        }
+    }
+
+    pub fn set_export_path(&mut self, path: impl Into<String>) {
+        self.export.get_or_insert_with(Export::default).path = Some(path.into());
+    }
+
+    /// The http client described by the `[network]` section, a configured proxy replaces the
+    /// one from the environment
+    pub fn client(&self) -> linux_mirrors::Result<Client> {
+        let network = match &self.network {
+            Some(network) => network,
+            None => return Client::builder().build(),
+        };
+        let mut proxy = match &network.proxy {
+            Some(url) => Proxy::all(url),
+            None => Proxy::from_env(),
+        };
+        if let Some(no_proxy) = &network.no_proxy {
+            proxy.no_proxy = no_proxy.clone();
+        }
+        let mut builder = Client::builder().proxy(proxy);
+        for bundle in network.ca_bundles.iter().flatten() {
+            builder = builder.ca_bundle(bundle);
+        }
+        match (&network.client_cert, &network.client_key) {
+            (Some(cert), Some(key)) => builder = builder.client_cert(cert, key),
+            (None, None) => {}
+            _ => {
+                return Err(Error::Config(
+                    "client-cert and client-key must be set together".to_owned(),
+                ))
+            }
+        }
+        builder.build()
+    }
+
+    /// What the available mirrors table is sorted by
+    pub fn sort(&self) -> (SortKey, SortDirection) {
+        match &self.sort {
+            Some(sort) => (
+                sort.key.unwrap_or_default(),
+                sort.direction.unwrap_or_default(),
+            ),
+            None => Default::default(),
+        }
+    }
+
+    pub fn set_sort(&mut self, key: SortKey, direction: SortDirection) {
+        self.sort = Some(Sort {
+            key: Some(key),
+            direction: Some(direction),
+        });
+    }
+
+    /// Writes the sort to the state file, the config file is left alone
+    pub fn save_sort(&self) -> io::Result<()> {
+        let path = match &self.state {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        let sort = toml::to_string(self.sort.as_ref().unwrap_or(&Sort::default()))
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        if let Some(dir) = path.parent().filter(|f| !f.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)?;
+        }
+        export::save(path, 0, |file| file.write_all(sort.as_bytes()))
+    }
+
+    /// Uses the sort remembered in the state file, if there is one
+    pub fn load_sort(&mut self) {
+        let path = match &self.state {
+            Some(path) => path,
+            None => return,
+        };
+        let sort = match fs::read_to_string(path) {
+            Ok(sort) => sort,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return,
+            Err(e) => {
+                warn!("{}: {}", path.display(), e);
+                return;
+            }
+        };
+        match toml::from_str(&sort) {
+            Ok(sort) => self.sort = Some(sort),
+            Err(e) => warn!("{}: {}", path.display(), e),
+        }
+    }
+
+    /// How often, and how patiently, the mirror status is fetched again when it fails
+    pub fn retry_policy(&self) -> RetryPolicy {
+        let mut policy = RetryPolicy::default();
+        if let Some(network) = &self.network {
+            if let Some(attempts) = network.attempts {
+                policy = policy.max_attempts(attempts);
+            }
+            if let Some(backoff) = network.backoff_ms {
+                policy = policy.backoff(Duration::from_millis(backoff));
+            }
+            if let Some(max_backoff) = network.max_backoff_ms {
+                policy = policy.max_backoff(Duration::from_millis(max_backoff));
+            }
+            if let Some(jitter) = network.jitter {
+                policy = policy.jitter(jitter);
+            }
+            if let Some(retry_on) = &network.retry_on {
+                policy = policy.retry_on(retry_on.iter().copied());
+            }
+        }
+        policy
+    }
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Sort {
+    pub key: Option<SortKey>,
+    pub direction: Option<SortDirection>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Network {
+    pub proxy: Option<String>,
+    #[serde(rename = "no-proxy")]
+    pub no_proxy: Option<Vec<String>>,
+    #[serde(rename = "ca-bundles")]
+    pub ca_bundles: Option<Vec<String>>,
+    #[serde(rename = "client-cert")]
+    pub client_cert: Option<String>,
+    #[serde(rename = "client-key")]
+    pub client_key: Option<String>,
+    /// Attempts at fetching the mirror status, including the first
+    pub attempts: Option<u32>,
+    #[serde(rename = "backoff-ms")]
+    pub backoff_ms: Option<u64>,
+    #[serde(rename = "max-backoff-ms")]
+    pub max_backoff_ms: Option<u64>,
+    /// Fraction of every wait taken off at random, between 0 and 1
+    pub jitter: Option<f64>,
+    /// The failures that are retried, all of them when not set
+    #[serde(rename = "retry-on")]
+    pub retry_on: Option<Vec<RetryOn>>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Export {
+    pub path: Option<String>,
+    pub backups: Option<usize>,
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Deserialize)]
@@ -61,6 +250,9 @@ pub struct Info {
     #[serde(rename = "last_checked")]
     pub last_checked: Option<String>,
     pub now: Option<String>,
+    pub source: Option<String>,
+    /// Used instead of the other colours for data that is out of date
+    pub stale: Option<String>,
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Deserialize)]
@@ -72,8 +264,80 @@ pub struct Icons {
     #[serde(rename = "last_checked")]
     pub last_checked: Option<String>,
     pub now: Option<String>,
+    pub source: Option<String>,
     #[serde(rename = "highlight-symbol-countries")]
     pub highlight_symbol_countries: Option<char>,
     #[serde(rename = "highlight-symbol-mirrors")]
     pub highlight_symbol_mirrors: Option<char>,
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use linux_mirrors::Error;
+
+    use super::{MirrorsConfig, RetryOn};
+    use crate::app::state::{SortDirection, SortKey};
+
+    #[test]
+    fn should_read_the_retry_policy() {
+        let config: MirrorsConfig = toml::from_str(
+            "[network]\nattempts = 5\nbackoff-ms = 100\nmax-backoff-ms = 300\njitter = 0.25\nretry-on = [\"timeout\", \"5xx\"]\n",
+        )
+        .unwrap();
+        assert_eq!(
+            config.network.as_ref().unwrap().retry_on,
+            Some(vec![RetryOn::Timeout, RetryOn::ServerError])
+        );
+        let policy = config.retry_policy();
+        assert_eq!(policy.backoff_for(1), Duration::from_millis(100));
+        assert_eq!(policy.backoff_for(3), Duration::from_millis(300));
+        let timeout = Error::Timeout(Duration::from_secs(30));
+        assert!(policy.retries(&timeout));
+
+        let config: MirrorsConfig = toml::from_str("[network]\nretry-on = [\"5xx\"]\n").unwrap();
+        assert!(!config.retry_policy().retries(&timeout));
+        // every transient failure without retry-on
+        assert!(MirrorsConfig::default().retry_policy().retries(&timeout));
+        assert!(toml::from_str::<MirrorsConfig>("[network]\nretry-on = [\"4xx\"]\n").is_err());
+    }
+
+    #[test]
+    fn should_remember_the_sort() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("mirro-rs").join("sort.toml");
+        let mut config = MirrorsConfig {
+            state: Some(path.clone()),
+            ..Default::default()
+        };
+        // nothing saved yet, the config's own sort is kept
+        config.set_sort(SortKey::Delay, SortDirection::Ascending);
+        config.load_sort();
+        assert_eq!(config.sort(), (SortKey::Delay, SortDirection::Ascending));
+
+        config.set_sort(SortKey::Https, SortDirection::Descending);
+        config.save_sort().unwrap();
+        config.set_sort(SortKey::Score, SortDirection::Ascending);
+        config.save_sort().unwrap();
+
+        let mut restarted: MirrorsConfig =
+            toml::from_str(include_str!("../../../mirro-rs.toml")).unwrap();
+        restarted.state = Some(path.clone());
+        restarted.load_sort();
+        assert_eq!(restarted.sort(), (SortKey::Score, SortDirection::Ascending));
+        // only the sort is in the state file
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "key = \"score\"\ndirection = \"ascending\"\n"
+        );
+
+        std::fs::write(&path, "key = 3\n").unwrap();
+        restarted.set_sort(SortKey::Mirrors, SortDirection::Descending);
+        restarted.load_sort();
+        assert_eq!(
+            restarted.sort(),
+            (SortKey::Mirrors, SortDirection::Descending)
+        );
+    }
+}
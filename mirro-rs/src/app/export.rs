@@ -1,25 +1,528 @@
-use std::{fs::OpenOptions, io::Write};
+use std::{
+    collections::{HashMap, HashSet},
+    fs::{self, File},
+    io::{self, Write},
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+};
 
-use super::state::SelectedCountry;
+use chrono::{DateTime, Local, NaiveDateTime, Utc};
+use clap::{crate_name, crate_version};
+use linux_mirrors::{
+    archlinux::rate::{rate, RateOptions, RatedMirror},
+    Client,
+};
+use tracing::{error, info};
+
+use super::state::{Protocols, SelectedCountry};
+
+/// What a mirrorlist was generated from, written as comments at the top of the file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Header {
+    pub when: DateTime<Utc>,
+    /// Filter name and the value it was set to, in the order they should be listed
+    pub filters: Vec<(String, String)>,
+    pub countries: Vec<String>,
+}
+
+/// A single `Server =` line and the country it is grouped under
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Server {
+    pub country: String,
+    pub url: String,
+}
+
+/// Writes `servers` in pacman's mirrorlist format.
+///
+/// Servers keep the order they are given in, a `## Country` comment is added every time the
+/// country changes from the previous server. Servers pacman cannot download from, such as rsync
+/// ones, are commented out.
+pub fn write_mirrorlist(
+    out: &mut impl Write,
+    header: &Header,
+    servers: &[Server],
+) -> io::Result<()> {
+    let title = format!(" Arch Linux mirrorlist generated by {} ", crate_name!());
+    writeln!(out, "{}", "#".repeat(80))?;
+    writeln!(out, "{:#^80}", title)?;
+    writeln!(out, "{}", "#".repeat(80))?;
+    writeln!(out)?;
+    writeln!(
+        out,
+        "# {:<11} {} {}",
+        "With:",
+        crate_name!(),
+        crate_version!()
+    )?;
+    writeln!(
+        out,
+        "# {:<11} {}",
+        "When:",
+        header.when.format("%Y-%m-%d %H:%M:%S %Z")
+    )?;
+    for (name, value) in header.filters.iter() {
+        writeln!(out, "# {:<11} {}", format!("{}:", name), value)?;
+    }
+    writeln!(
+        out,
+        "# {:<11} {}",
+        "Countries:",
+        header.countries.join(", ")
+    )?;
+
+    let mut country = None;
+    for server in servers.iter() {
+        if country != Some(&server.country) {
+            writeln!(out)?;
+            writeln!(
+                out,
+                "## {}",
+                if server.country.is_empty() {
+                    "Worldwide"
+                } else {
+                    &server.country
+                }
+            )?;
+            country = Some(&server.country);
+        }
+        let line = format!(
+            "Server = {}/$repo/os/$arch",
+            server.url.trim_end_matches('/')
+        );
+        match pacman_can_fetch(&server.url) {
+            true => writeln!(out, "{}", line)?,
+            false => writeln!(out, "#{}  (pacman cannot download from it)", line)?,
+        }
+    }
+    Ok(())
+}
+
+/// Whether pacman can download packages from `url`, it cannot from rsync mirrors
+pub fn pacman_can_fetch(url: &str) -> bool {
+    match url.split_once("://") {
+        Some((scheme, _)) => ["http", "https", "ftp", "file"]
+            .iter()
+            .any(|f| scheme.eq_ignore_ascii_case(f)),
+        None => false,
+    }
+}
+
+/// Replaces `path` with what `write` produces.
+///
+/// The new contents go to a temporary file in the same directory which is then renamed over
+/// `path`, so readers only ever see the old or the new file. If `path` already exists it is
+/// copied to `<path>.<timestamp>.bak` first and only the newest `backups` copies are kept.
+pub fn save(
+    path: &Path,
+    backups: usize,
+    write: impl FnOnce(&mut File) -> io::Result<()>,
+) -> io::Result<()> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    let name = path
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "path has no file name"))?
+        .to_string_lossy()
+        .to_string();
+    // unique per export, concurrent exports never share one. It is removed when dropped
+    let mut temp = tempfile::Builder::new()
+        .prefix(&format!(".{}.", name))
+        .suffix(".tmp")
+        .tempfile_in(&dir)?;
+    write(temp.as_file_mut())?;
+    temp.as_file().sync_all()?;
+
+    match fs::metadata(path) {
+        Ok(metadata) => {
+            fs::set_permissions(temp.path(), metadata.permissions())?;
+            if backups > 0 {
+                let stamp = Local::now().format(BACKUP_STAMP);
+                fs::copy(path, dir.join(format!("{}.{}.bak", name, stamp)))?;
+            }
+        }
+        // temporary files are only readable by their owner
+        Err(_) => fs::set_permissions(temp.path(), fs::Permissions::from_mode(0o644))?,
+    }
+    temp.persist(path).map_err(|f| f.error)?;
+    prune_backups(&dir, &name, backups)
+}
+
+/// The timestamp in the names of the backups [`save`] makes
+const BACKUP_STAMP: &str = "%Y%m%d-%H%M%S%.3f";
+
+/// Removes all but the newest `keep` backups of `name` made by [`save`], other `.bak` files
+/// are left alone
+fn prune_backups(dir: &Path, name: &str, keep: usize) -> io::Result<()> {
+    let prefix = format!("{}.", name);
+    let mut backups: Vec<_> = fs::read_dir(dir)?
+        .filter_map(|f| f.ok())
+        .map(|f| f.file_name().to_string_lossy().to_string())
+        .filter(|f| {
+            f.strip_prefix(&prefix)
+                .and_then(|f| f.strip_suffix(".bak"))
+                .is_some_and(|stamp| {
+                    // `%.3f` also parses fewer digits, the length pins it to what is written
+                    stamp.len() == "20220213-120804.123".len()
+                        && NaiveDateTime::parse_from_str(stamp, BACKUP_STAMP).is_ok()
+                })
+        })
+        .collect();
+    // the timestamps sort chronologically, newest last
+    backups.sort();
+    let stale = backups.len().saturating_sub(keep);
+    for backup in backups.iter().take(stale) {
+        fs::remove_file(dir.join(backup))?;
+    }
+    Ok(())
+}
+
+/// Exactly the mirrors picked in `selected_countries` over a checked protocol, in the order they
+/// are listed
+pub fn picked_servers(
+    selected_countries: &[SelectedCountry],
+    protocols: &Protocols,
+) -> Vec<Server> {
+    selected_countries
+        .iter()
+        .flat_map(|f| {
+            f.picked()
+                .filter(|m| protocols.allows(&m.protocol))
+                .map(|m| Server {
+                    country: f.country.country.clone(),
+                    url: m.url.clone(),
+                })
+        })
+        .collect()
+}
+
+/// The servers that could be rated, fastest first or, when `hand_ordered`, still in the order
+/// they were picked in
+pub fn rated_servers(
+    servers: Vec<Server>,
+    rated: &[RatedMirror],
+    hand_ordered: bool,
+) -> Vec<Server> {
+    if hand_ordered {
+        let rated: HashSet<_> = rated.iter().map(|f| f.mirror.url.as_str()).collect();
+        return servers
+            .into_iter()
+            .filter(|f| rated.contains(f.url.as_str()))
+            .collect();
+    }
+    let mut country_of: HashMap<_, _> = servers.into_iter().map(|f| (f.url, f.country)).collect();
+    rated
+        .iter()
+        .filter_map(|f| {
+            country_of.remove(&f.mirror.url).map(|country| Server {
+                country,
+                url: f.mirror.url.clone(),
+            })
+        })
+        .collect()
+}
 
 pub(crate) async fn export_mirrors(
     selected_countries: Vec<SelectedCountry>,
-    rate: bool,
+    protocols: Protocols,
+    rate_mirrors: bool,
+    hand_ordered: bool,
+    client: Client,
+    path: PathBuf,
+    backups: usize,
 ) -> super::AppReturn {
-    match rate {
-        true => todo!(),
-        false => tokio::spawn(async move {
-            let mut fs = OpenOptions::new()
-                .create(true)
-                .append(true)
-                .open("my_mirrors")
-                .unwrap();
-            for i in selected_countries.iter() {
-                for x in i.country.mirrors.iter() {
-                    writeln!(fs, "{}", &x.url).unwrap();
-                }
-            }
-        }),
-    };
+    tokio::spawn(async move {
+        let enabled: Vec<_> = protocols.enabled().iter().map(|f| f.to_string()).collect();
+        let mut filters = vec![("Protocols".to_owned(), enabled.join(", "))];
+        let countries = selected_countries
+            .iter()
+            .map(|f| f.country.country.clone())
+            .collect();
+        let mut servers = picked_servers(&selected_countries, &protocols);
+        if rate_mirrors {
+            let mirrors = selected_countries
+                .iter()
+                .flat_map(|f| f.picked())
+                .filter(|f| protocols.allows(&f.protocol))
+                .cloned()
+                .collect();
+            let rated = rate(&client, mirrors, &RateOptions::default()).await;
+            info!("rated {} of {} mirrors", rated.len(), servers.len());
+            servers = rated_servers(servers, &rated, hand_ordered);
+            let order = match hand_ordered {
+                true => "kept in the order set by hand",
+                false => "sorted by download speed",
+            };
+            filters.push((
+                "Rated".to_owned(),
+                format!(
+                    "{}, mirrors that could not be downloaded from are left out",
+                    order
+                ),
+            ));
+        }
+        let header = Header {
+            when: Utc::now(),
+            filters,
+            countries,
+        };
+        match save(&path, backups, |fs| write_mirrorlist(fs, &header, &servers)) {
+            Ok(_) => info!("exported {} mirrors to {}", servers.len(), path.display()),
+            Err(e) => error!("{}: {e}", path.display()),
+        }
+    });
     super::AppReturn::Continue
 }
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+
+    use super::*;
+
+    fn server(country: &str, url: &str) -> Server {
+        Server {
+            country: country.to_owned(),
+            url: url.to_owned(),
+        }
+    }
+
+    #[test]
+    fn should_export_exactly_the_picked_mirrors() {
+        use linux_mirrors::archlinux::internal::{Mirror, Protocol, Url};
+
+        let mirror = |url: &str| {
+            let mut mirror = Mirror::default();
+            mirror.url = url.to_owned();
+            mirror.protocol = Protocol::from(url.split_once("://").unwrap().0);
+            mirror
+        };
+        let country = Url {
+            country: "Germany".to_owned(),
+            country_code: "DE".to_owned(),
+            mirrors: vec![
+                mirror("https://mirror.one.de/"),
+                mirror("rsync://mirror.one.de/"),
+                mirror("https://mirror.two.de/"),
+            ],
+        };
+        let mut protocols = Protocols::default();
+        let mut selected = SelectedCountry::new(country, String::new(), 0);
+        assert!(picked_servers(&[selected.clone()], &protocols).is_empty());
+        selected.toggle(&mirror("https://mirror.two.de/"));
+        selected.toggle(&mirror("https://mirror.one.de/"));
+        // in the order they were picked
+        assert_eq!(
+            picked_servers(&[selected.clone()], &protocols),
+            [
+                server("Germany", "https://mirror.two.de/"),
+                server("Germany", "https://mirror.one.de/"),
+            ]
+        );
+        selected.toggle(&mirror("https://mirror.one.de/"));
+        assert_eq!(
+            picked_servers(&[selected.clone()], &protocols),
+            [server("Germany", "https://mirror.two.de/")]
+        );
+
+        // mirrors picked over a protocol that was unchecked since are left out
+        protocols.rsync = true;
+        selected.toggle(&mirror("rsync://mirror.one.de/"));
+        assert_eq!(picked_servers(&[selected.clone()], &protocols).len(), 2);
+        protocols.rsync = false;
+        assert_eq!(
+            picked_servers(&[selected], &protocols),
+            [server("Germany", "https://mirror.two.de/")]
+        );
+    }
+
+    #[test]
+    fn should_rank_by_speed_unless_ordered_by_hand() {
+        use linux_mirrors::archlinux::internal::Mirror;
+
+        let rated = |url: &str, millis| RatedMirror {
+            mirror: {
+                let mut mirror = Mirror::default();
+                mirror.url = url.to_owned();
+                mirror
+            },
+            bytes: 1000,
+            elapsed: std::time::Duration::from_millis(millis),
+        };
+        let servers = vec![
+            server("Germany", "https://slow.de/"),
+            server("Germany", "https://down.de/"),
+            server("France", "https://fast.fr/"),
+        ];
+        // rated fastest first, the mirror that failed is missing
+        let rated = [
+            rated("https://fast.fr/", 10),
+            rated("https://slow.de/", 900),
+        ];
+        assert_eq!(
+            rated_servers(servers.clone(), &rated, true),
+            [
+                server("Germany", "https://slow.de/"),
+                server("France", "https://fast.fr/")
+            ]
+        );
+        assert_eq!(
+            rated_servers(servers, &rated, false),
+            [
+                server("France", "https://fast.fr/"),
+                server("Germany", "https://slow.de/")
+            ]
+        );
+    }
+
+    #[test]
+    fn should_write_pacman_mirrorlist() {
+        let header = Header {
+            when: Utc.ymd(2022, 2, 13).and_hms(12, 8, 4),
+            filters: vec![("Protocols".to_owned(), "https, http".to_owned())],
+            countries: vec!["Germany".to_owned(), "France".to_owned()],
+        };
+        let servers = vec![
+            server("Germany", "https://mirror.one.de/archlinux/"),
+            server("Germany", "http://mirror.two.de/archlinux"),
+            server("France", "https://mirror.fr/archlinux/"),
+            server("Germany", "https://mirror.three.de/archlinux/"),
+            server("", "https://geo.mirror.pkgbuild.com/"),
+            server("", "rsync://rsync.mirror.pkgbuild.com/archlinux/"),
+        ];
+        let mut out = Vec::new();
+        write_mirrorlist(&mut out, &header, &servers).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let expected = format!(
+            "{hashes}
+################# Arch Linux mirrorlist generated by mirro-rs ##################
+{hashes}
+
+# With:       mirro-rs {version}
+# When:       2022-02-13 12:08:04 UTC
+# Protocols:  https, http
+# Countries:  Germany, France
+
+## Germany
+Server = https://mirror.one.de/archlinux/$repo/os/$arch
+Server = http://mirror.two.de/archlinux/$repo/os/$arch
+
+## France
+Server = https://mirror.fr/archlinux/$repo/os/$arch
+
+## Germany
+Server = https://mirror.three.de/archlinux/$repo/os/$arch
+
+## Worldwide
+Server = https://geo.mirror.pkgbuild.com/$repo/os/$arch
+#Server = rsync://rsync.mirror.pkgbuild.com/archlinux/$repo/os/$arch  (pacman cannot download from it)
+",
+            hashes = "#".repeat(80),
+            version = crate_version!()
+        );
+        assert_eq!(out, expected);
+    }
+
+    #[test]
+    fn should_write_header_without_servers() {
+        let header = Header {
+            when: Utc.ymd(2022, 2, 13).and_hms(12, 8, 4),
+            filters: vec![],
+            countries: vec![],
+        };
+        let mut out = Vec::new();
+        write_mirrorlist(&mut out, &header, &[]).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.ends_with("# Countries:  \n"));
+        assert!(!out.contains("Server ="));
+    }
+
+    fn backups(dir: &Path) -> Vec<String> {
+        let mut files: Vec<_> = fs::read_dir(dir)
+            .unwrap()
+            .map(|f| f.unwrap().file_name().to_string_lossy().to_string())
+            .filter(|f| f.ends_with(".bak"))
+            .collect();
+        files.sort();
+        files
+    }
+
+    #[test]
+    fn should_replace_file_and_keep_backups() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("mirrorlist");
+        for i in 0..4 {
+            save(&path, 2, |fs| write!(fs, "export {}", i)).unwrap();
+            std::thread::sleep(std::time::Duration::from_millis(5));
+        }
+        assert_eq!(fs::read_to_string(&path).unwrap(), "export 3");
+        let backups = backups(dir.path());
+        assert_eq!(backups.len(), 2);
+        assert_eq!(
+            fs::read_to_string(dir.path().join(&backups[0])).unwrap(),
+            "export 1"
+        );
+        assert_eq!(
+            fs::read_to_string(dir.path().join(&backups[1])).unwrap(),
+            "export 2"
+        );
+    }
+
+    #[test]
+    fn should_only_prune_its_own_backups() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("mirrorlist");
+        let others = [
+            "mirrorlist.bak",
+            "mirrorlist.pacnew.bak",
+            "mirrorlist.20220213-120804.bak",
+            "mirrorlist.20220213-120804.123.old.bak",
+        ];
+        for other in others {
+            fs::write(dir.path().join(other), "mine").unwrap();
+        }
+        for i in 0..3 {
+            save(&path, 1, |fs| write!(fs, "export {}", i)).unwrap();
+            std::thread::sleep(std::time::Duration::from_millis(5));
+        }
+        for other in others {
+            assert!(dir.path().join(other).exists(), "{} was removed", other);
+        }
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), others.len() + 2);
+    }
+
+    #[test]
+    fn should_not_share_temporary_files_between_exports() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("mirrorlist");
+        let threads: Vec<_> = (0..8)
+            .map(|i| {
+                let path = path.clone();
+                std::thread::spawn(move || {
+                    save(&path, 0, |fs| {
+                        std::thread::sleep(std::time::Duration::from_millis(10));
+                        write!(fs, "export {}", i)
+                    })
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap().unwrap();
+        }
+        let contents = fs::read_to_string(&path).unwrap();
+        assert!(contents.starts_with("export ") && contents.len() == "export 0".len());
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn should_leave_file_untouched_when_write_fails() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("mirrorlist");
+        save(&path, 0, |fs| write!(fs, "original")).unwrap();
+        let res = save(&path, 3, |_| Err(io::Error::other("boom")));
+        assert!(res.is_err());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "original");
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+}
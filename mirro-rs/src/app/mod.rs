@@ -1,22 +1,40 @@
-use chrono::{DateTime, Local};
-use linux_mirrors::archlinux::internal::{ArchMirrors, Url};
-use tracing::{error, trace};
+use std::cmp::Ordering;
+
+use chrono::{DateTime, Duration, Local, Utc};
+use linux_mirrors::{
+    archlinux::internal::{ArchMirrors, Mirror, Url},
+    Client,
+};
+use tracing::{error, trace, warn};
 use tui::widgets::TableState;
 
-use crate::{inputs::key::Key, io::IoEvent};
+use crate::{
+    inputs::{key::Key, mouse::Mouse},
+    io::IoEvent,
+};
 
 use self::{
     actions::{Action, Actions},
+    areas::Areas,
     config::MirrorsConfig,
-    state::{AppState, SelectedCountry, Widgets},
+    search::{Match, Search},
+    state::{
+        AppState, CountryStats, Protocols, Provenance, SelectedCountry, SortDirection, SortKey,
+        Widgets,
+    },
 };
 
 pub mod actions;
+mod areas;
 pub mod config;
 pub mod export;
+pub mod search;
 pub mod state;
 pub mod ui;
 
+/// Mirror data that was not fetched on this launch is stale once it is older than this
+const STALE_AFTER_HOURS: i64 = 24;
+
 #[derive(Debug, PartialEq, Eq)]
 pub enum AppReturn {
     Exit,
@@ -27,6 +45,7 @@ pub enum AppReturn {
 enum ScrollableTables {
     AllMirrors,
     SavedMirrors,
+    FocusedCountry,
 }
 
 /// The main application, containing the state
@@ -37,12 +56,33 @@ pub struct App {
     io_tx: tokio::sync::mpsc::Sender<IoEvent>,
     state: AppState,
     mirrors: ArchMirrors,
+    provenance: Provenance,
+    /// Why the mirror status could not be fetched
+    pub(crate) fetch_error: Option<String>,
+    /// How fetching the status is going while it is being retried
+    pub(crate) fetch_progress: Option<String>,
+    /// What else the user should know about the status, such as mirrors left out of it
+    pub(crate) fetch_note: Option<String>,
+    /// An export of stale data waiting for the same keys to be pressed again
+    pending_export: Option<Action>,
+    /// Why the last export was not started
+    export_error: Option<String>,
     country_filter: String,
+    /// The country filter, or why it is not a valid regular expression
+    search: Result<Search, regex::Error>,
+    protocols: Protocols,
     clock: DateTime<Local>,
     table: TableState,
     selected_countries: Vec<SelectedCountry>,
     selected_table: TableState,
     focused_country: Url,
+    /// The cursor in the focused country's mirrors
+    mirrors_table: TableState,
+    /// Where the widgets were last drawn
+    areas: Areas,
+    /// Whether the marked countries, or their mirrors, were moved or sorted. Rated exports
+    /// then keep this order instead of ranking by speed
+    hand_ordered: bool,
     config: MirrorsConfig,
 }
 
@@ -58,12 +98,23 @@ impl App {
             is_loading,
             state,
             mirrors: ArchMirrors::default(),
+            provenance: Provenance::default(),
+            fetch_error: None,
+            fetch_progress: None,
+            fetch_note: None,
+            pending_export: None,
+            export_error: None,
             country_filter: String::default(),
+            search: Ok(Search::All),
+            protocols: Protocols::default(),
             clock: Local::now(),
             table: TableState::default(),
             selected_table: TableState::default(),
             selected_countries: vec![],
             focused_country: Url::default(),
+            mirrors_table: TableState::default(),
+            areas: Areas::default(),
+            hand_ordered: false,
             config,
         }
     }
@@ -78,6 +129,10 @@ impl App {
         }
     }
 
+    pub async fn do_mouse(&mut self, mouse: Mouse) -> AppReturn {
+        mouse_handler(self, mouse)
+    }
+
     pub async fn update_on_tick(&mut self) -> AppReturn {
         // TODO update clock
         AppReturn::Continue
@@ -102,6 +157,8 @@ impl App {
             Action::Focus(Widgets::Protocols),
             Action::Focus(Widgets::Mirrors),
             Action::Focus(Widgets::SelectedCountries),
+            Action::Focus(Widgets::CountryMirrors),
+            Action::Focus(Widgets::MirrorDetails),
             Action::Action,
             Action::SimpleExport,
             Action::RateExport,
@@ -122,12 +179,78 @@ impl App {
         self.clock = clock;
     }
 
-    pub fn update_mirrors(&mut self, mirrors: &ArchMirrors) {
+    pub fn update_mirrors(&mut self, mirrors: &ArchMirrors, provenance: Provenance) {
         self.mirrors = mirrors.clone();
+        self.provenance = provenance;
+    }
+
+    pub fn provenance(&self) -> Provenance {
+        self.provenance
+    }
+
+    pub fn fetch_error(&self) -> Option<&str> {
+        self.fetch_error.as_deref()
+    }
+
+    pub fn fetch_progress(&self) -> Option<&str> {
+        self.fetch_progress.as_deref()
+    }
+
+    pub fn fetch_note(&self) -> Option<&str> {
+        self.fetch_note.as_deref()
+    }
+
+    /// Time since archlinux.org last checked the mirrors on screen
+    pub fn data_age(&self) -> Duration {
+        self.clock.with_timezone(&Utc) - self.mirrors.last_check
+    }
+
+    pub fn is_stale(&self) -> bool {
+        self.provenance != Provenance::Live && self.data_age() > Duration::hours(STALE_AFTER_HOURS)
+    }
+
+    /// Whether `export` should go ahead, stale data is only exported when asked for twice in a
+    /// row
+    fn confirm_export(&mut self, export: Action) -> bool {
+        if self.is_stale() && self.pending_export != Some(export) {
+            warn!(
+                "{} mirror data is {} hours old, waiting for confirmation",
+                self.provenance,
+                self.data_age().num_hours()
+            );
+            self.pending_export = Some(export);
+            false
+        } else {
+            self.pending_export = None;
+            true
+        }
+    }
+
+    pub fn pending_export(&self) -> Option<Action> {
+        self.pending_export
+    }
+
+    /// The client described by the config, the export is not started when it is invalid
+    fn export_client(&mut self) -> Option<Client> {
+        match self.config.client() {
+            Ok(client) => Some(client),
+            Err(e) => {
+                error!("not exporting, invalid network config: {}", e);
+                self.export_error = Some(format!("Not exported, invalid [network] config: {}", e));
+                None
+            }
+        }
+    }
+
+    pub fn export_error(&self) -> Option<&str> {
+        self.export_error.as_deref()
     }
 
     fn scroll_prev(&mut self, table: ScrollableTables) {
         let (state, items) = self.table_info(table);
+        if items == 0 {
+            return;
+        }
         let i = match state.selected() {
             Some(i) => {
                 if i >= items - 1 {
@@ -144,6 +267,9 @@ impl App {
 
     fn scroll_next(&mut self, table: ScrollableTables) {
         let (state, items) = self.table_info(table);
+        if items == 0 {
+            return;
+        }
         let i = match state.selected() {
             Some(i) => {
                 if i == 0 {
@@ -159,32 +285,392 @@ impl App {
     }
 
     fn update_mirrors_widget(&mut self, table: ScrollableTables, index: usize) {
+        let focused = match table {
+            ScrollableTables::AllMirrors if self.searching_mirrors() => {
+                let (country, mirror) = match self.mirror_matches().get(index) {
+                    Some((country, mirror, _)) => ((*country).clone(), mirror.url.clone()),
+                    None => return,
+                };
+                self.focused_country = country;
+                let position = self.focused_mirrors().iter().position(|f| f.url == mirror);
+                self.mirrors_table.select(position);
+                return;
+            }
+            ScrollableTables::AllMirrors => {
+                self.filtered_countries().get(index).map(|f| (*f).clone())
+            }
+            ScrollableTables::SavedMirrors => self
+                .selected_countries
+                .get(index)
+                .map(|f| f.country.clone()),
+            ScrollableTables::FocusedCountry => None,
+        };
+        if let Some(country) = focused {
+            self.focused_country = country;
+            self.mirrors_table.select(None);
+        }
+    }
+
+    /// What Enter does on the `index`th row of `table`
+    fn activate(&mut self, table: ScrollableTables, index: usize) {
         match table {
-            ScrollableTables::AllMirrors => {}
+            ScrollableTables::AllMirrors if self.searching_mirrors() => {
+                self.toggle_found_mirror(index)
+            }
+            ScrollableTables::AllMirrors => self.select_country(index),
             ScrollableTables::SavedMirrors => {
-                if let Some(country) = self.selected_countries.get(index) {
-                    self.focused_country = country.country.clone()
+                if !self.selected_countries.is_empty() {
+                    self.selected_countries.remove(index);
+                    let len = self.selected_countries.len();
+                    if len != 0 {
+                        self.selected_table.select(Some(index.min(len - 1)));
+                    } else {
+                        self.selected_table.select(None)
+                    }
                 }
             }
+            ScrollableTables::FocusedCountry => self.toggle_mirror(index),
+        }
+    }
+
+    /// Moves the cursor of `table` to the row at `column` and `row`, clicking the row under the
+    /// cursor activates it
+    fn click_row(&mut self, table: ScrollableTables, column: u16, row: u16) {
+        let (state, len) = self.table_info(table);
+        let selected = state.selected();
+        let index = match self.areas.table(table).row_at(column, row, len) {
+            Some(index) => index,
+            None => return,
+        };
+        if selected == Some(index) {
+            self.activate(table, index);
+        } else {
+            self.table_info(table).0.select(Some(index));
+            self.update_mirrors_widget(table, index);
+        }
+    }
+
+    fn toggle_protocol(&mut self) {
+        self.protocols.toggle_highlighted();
+        if self.table.selected().is_some() {
+            self.table.select(None);
         }
     }
 
     fn table_info(&mut self, table: ScrollableTables) -> (&mut TableState, usize) {
         match table {
-            ScrollableTables::AllMirrors => (&mut self.table, self.mirrors.countries.len()),
+            ScrollableTables::AllMirrors => {
+                let items = match self.searching_mirrors() {
+                    true => self.mirror_matches().len(),
+                    false => self.filtered_countries().len(),
+                };
+                (&mut self.table, items)
+            }
             ScrollableTables::SavedMirrors => {
                 (&mut self.selected_table, self.selected_countries.len())
             }
+            ScrollableTables::FocusedCountry => {
+                let items = self.focused_mirrors().len();
+                (&mut self.mirrors_table, items)
+            }
         }
     }
 
     pub fn focused_country(&self) -> &Url {
         &self.focused_country
     }
+
+    /// Mirrors of the focused country with a checked protocol, in the order they are drawn
+    pub fn focused_mirrors(&self) -> Vec<&Mirror> {
+        self.focused_country
+            .mirrors
+            .iter()
+            .filter(|f| self.protocols.allows(&f.protocol))
+            .collect()
+    }
+
+    /// The mirror under the cursor in the focused country's mirrors
+    pub fn highlighted_mirror(&self) -> Option<&Mirror> {
+        let index = self.mirrors_table.selected()?;
+        self.focused_mirrors().get(index).copied()
+    }
+
+    /// What is marked for saving of `country`
+    pub fn selection(&self, country: &Url) -> Option<&SelectedCountry> {
+        self.selected_countries
+            .iter()
+            .find(|f| f.country.country == country.country)
+    }
+
+    /// Marks the `index`th available country, picking its active mirrors that have a checked
+    /// protocol. A country that is already marked keeps the mirrors picked in it
+    fn select_country(&mut self, index: usize) {
+        let country = match self.filtered_countries().get(index) {
+            Some(country) => (*country).clone(),
+            None => return,
+        };
+        if self.selection(&country).is_some() {
+            return;
+        }
+        let picked = country
+            .mirrors
+            .iter()
+            .filter(|f| f.active && self.protocols.allows(&f.protocol))
+            .map(|f| f.url.clone())
+            .collect();
+        let mut selected = SelectedCountry::new(
+            country,
+            self.country_filter.clone(),
+            index.try_into().unwrap_or(u8::MAX),
+        );
+        selected.mirrors = picked;
+        self.selected_countries.push(selected);
+    }
+
+    /// Picks, or unpicks, the `index`th mirror of the focused country. The country is marked
+    /// with its first picked mirror and unmarked with its last
+    fn toggle_mirror(&mut self, index: usize) {
+        let mirror = match self.focused_mirrors().get(index) {
+            Some(mirror) => (*mirror).clone(),
+            None => return,
+        };
+        let position = self
+            .selected_countries
+            .iter()
+            .position(|f| f.country.country == self.focused_country.country);
+        let position = match position {
+            Some(position) => position,
+            None => {
+                let index = self
+                    .filtered_countries()
+                    .iter()
+                    .position(|f| f.country == self.focused_country.country)
+                    .unwrap_or_default();
+                self.selected_countries.push(SelectedCountry::new(
+                    self.focused_country.clone(),
+                    self.country_filter.clone(),
+                    index.try_into().unwrap_or(u8::MAX),
+                ));
+                self.selected_countries.len() - 1
+            }
+        };
+        let selected = &mut self.selected_countries[position];
+        selected.toggle(&mirror);
+        if selected.mirrors.is_empty() {
+            self.selected_countries.remove(position);
+            if self.selected_countries.is_empty() {
+                self.selected_table.select(None);
+            } else if let Some(selected) = self.selected_table.selected() {
+                self.selected_table
+                    .select(Some(selected.min(self.selected_countries.len() - 1)));
+            }
+        }
+    }
+
+    /// Changes the country filter, see [`Search::new`]
+    fn search_for(&mut self, filter: String) {
+        self.search = Search::new(&filter);
+        self.country_filter = filter;
+        if self.table.selected().is_some() {
+            self.table.select(None);
+        }
+    }
+
+    pub fn search(&self) -> &Result<Search, regex::Error> {
+        &self.search
+    }
+
+    /// The countries matching the filter, best matches first and in the order of the
+    /// configured sort otherwise. An invalid regular expression matches nothing
+    pub fn country_matches(&self) -> Vec<(&Url, Match)> {
+        let search = match &self.search {
+            Ok(search) => search,
+            Err(_) => return vec![],
+        };
+        // the query matches every protocol when none is given
+        let protocols = self.protocols.enabled();
+        if protocols.is_empty() {
+            return vec![];
+        }
+        let (key, direction) = self.config.sort();
+        let mut countries: Vec<_> = self
+            .mirrors
+            .query()
+            .protocols(protocols)
+            .countries()
+            .into_iter()
+            .filter_map(|f| {
+                search
+                    .matches(f)
+                    .map(|found| (CountryStats::new(f, &self.protocols), f, found))
+            })
+            .collect();
+        countries.sort_by(|(a_stats, a, a_match), (b_stats, b, b_match)| {
+            b_match
+                .score
+                .cmp(&a_match.score)
+                .then_with(|| sort_order((a_stats, a), (b_stats, b), key, direction))
+        });
+        countries
+            .into_iter()
+            .map(|(_, f, found)| (f, found))
+            .collect()
+    }
+
+    /// Countries matching the filter that have at least one mirror with a checked protocol, in
+    /// the order they are drawn in the available mirrors table
+    pub fn filtered_countries(&self) -> Vec<&Url> {
+        self.country_matches().into_iter().map(|(f, _)| f).collect()
+    }
+
+    /// Exports the country under the cursor of the marked countries one place earlier, or
+    /// later
+    fn shift_country(&mut self, up: bool) {
+        self.hand_ordered = true;
+        if let Some(index) = self.selected_table.selected() {
+            let to = state::shift(&mut self.selected_countries, index, up);
+            self.selected_table.select(Some(to));
+        }
+    }
+
+    /// Exports the mirror under the cursor one place earlier, or later, among the mirrors of its
+    /// country
+    fn shift_mirror(&mut self, up: bool) {
+        let mirror = match self.highlighted_mirror() {
+            Some(mirror) => mirror.clone(),
+            None => return,
+        };
+        let country = &self.focused_country.country;
+        if let Some(selected) = self
+            .selected_countries
+            .iter_mut()
+            .find(|f| f.country.country == *country)
+        {
+            selected.shift(&mirror, up);
+            self.hand_ordered = true;
+        }
+    }
+
+    /// Exports the mirror under the cursor before any other, picking it if needed
+    fn pin_mirror(&mut self) {
+        let (index, mirror) = match self.mirrors_table.selected() {
+            Some(index) => match self.focused_mirrors().get(index) {
+                Some(mirror) => (index, (*mirror).clone()),
+                None => return,
+            },
+            None => return,
+        };
+        if self
+            .selection(&self.focused_country)
+            .is_none_or(|f| !f.is_picked(&mirror))
+        {
+            self.toggle_mirror(index);
+        }
+        let country = &self.focused_country.country;
+        if let Some(position) = self
+            .selected_countries
+            .iter()
+            .position(|f| f.country.country == *country)
+        {
+            let mut selected = self.selected_countries.remove(position);
+            selected.pin(&mirror);
+            self.selected_countries.insert(0, selected);
+            self.hand_ordered = true;
+            if self.selected_table.selected().is_some() {
+                self.selected_table.select(Some(0));
+            }
+        }
+    }
+
+    /// Orders the marked countries, and the mirrors picked in each, by the sort of the
+    /// available mirrors table
+    fn sort_selection(&mut self) {
+        self.hand_ordered = true;
+        let (key, direction) = self.config.sort();
+        let protocols = &self.protocols;
+        let mut selected: Vec<_> = self
+            .selected_countries
+            .drain(..)
+            .map(|f| (CountryStats::new(&f.country, protocols), f))
+            .collect();
+        selected.sort_by(|(a_stats, a), (b_stats, b)| {
+            sort_order((a_stats, &a.country), (b_stats, &b.country), key, direction)
+        });
+        self.selected_countries = selected
+            .into_iter()
+            .map(|(_, mut f)| {
+                f.sort(key, direction);
+                f
+            })
+            .collect();
+    }
+
+    /// Whether the available mirrors table lists the mirrors found by a hostname search
+    pub fn searching_mirrors(&self) -> bool {
+        self.search.as_ref().is_ok_and(Search::is_mirrors)
+    }
+
+    /// The mirrors with a checked protocol whose hostname matches the filter, with their
+    /// country, best matches first
+    pub fn mirror_matches(&self) -> Vec<(&Url, &Mirror, Match)> {
+        let search = match &self.search {
+            Ok(search) if search.is_mirrors() => search,
+            _ => return vec![],
+        };
+        let mut found: Vec<_> = self
+            .mirrors
+            .countries
+            .iter()
+            .flat_map(|country| country.mirrors.iter().map(move |f| (country, f)))
+            .filter(|(_, f)| self.protocols.allows(&f.protocol))
+            .filter_map(|(country, f)| search.matches_mirror(f).map(|found| (country, f, found)))
+            .collect();
+        found.sort_by(|(_, a, a_match), (_, b, b_match)| {
+            b_match
+                .score
+                .cmp(&a_match.score)
+                .then_with(|| a.url.cmp(&b.url))
+        });
+        found
+    }
+
+    /// Picks, or unpicks, the `index`th mirror found by a hostname search
+    fn toggle_found_mirror(&mut self, index: usize) {
+        self.update_mirrors_widget(ScrollableTables::AllMirrors, index);
+        if let Some(index) = self.mirrors_table.selected() {
+            self.toggle_mirror(index);
+        }
+    }
+
+    /// Sorts the available mirrors by the next column, or in the other direction, and
+    /// remembers it in the config file
+    pub fn change_sort(&mut self, key: SortKey, direction: SortDirection) {
+        self.config.set_sort(key, direction);
+        self.table.select(None);
+        if let Err(e) = self.config.save_sort() {
+            error!("could not save the sort: {}", e);
+        }
+    }
+}
+
+/// How countries are ordered when sorted by `key`, by name when the values are equal
+fn sort_order(
+    (a_stats, a): (&CountryStats, &Url),
+    (b_stats, b): (&CountryStats, &Url),
+    key: SortKey,
+    direction: SortDirection,
+) -> Ordering {
+    a_stats
+        .compare(b_stats, key, direction)
+        .then_with(|| direction.apply(a.country.cmp(&b.country)))
 }
 
 async fn key_handler(action: Action, app: &mut App, key: Key) -> AppReturn {
     if let Some(focused_widget) = app.state.focused_widget() {
+        if !matches!(action, Action::SimpleExport | Action::RateExport) {
+            app.pending_export = None;
+        }
+        app.export_error = None;
         match action {
             Action::Quit => AppReturn::Exit,
             Action::Sleep => {
@@ -215,91 +701,593 @@ async fn key_handler(action: Action, app: &mut App, key: Key) -> AppReturn {
                     app.state.update_focused_widget(Widgets::SelectedCountries);
                     AppReturn::Continue
                 }
+                Widgets::CountryMirrors => {
+                    trace!("country mirrors widget focused");
+                    app.state.update_focused_widget(Widgets::CountryMirrors);
+                    AppReturn::Continue
+                }
+                Widgets::MirrorDetails => {
+                    trace!("mirror details widget focused");
+                    if app.mirrors_table.selected().is_none() {
+                        app.scroll_prev(ScrollableTables::FocusedCountry);
+                    }
+                    app.state.update_focused_widget(Widgets::MirrorDetails);
+                    AppReturn::Continue
+                }
             },
             Action::Action => {
                 match focused_widget {
                     Widgets::CountryFilter => match key {
                         Key::Backspace => {
-                            app.country_filter.pop();
-                            if app.table.selected().is_some() {
-                                app.table.select(None);
-                            }
+                            let mut filter = app.country_filter.clone();
+                            filter.pop();
+                            app.search_for(filter);
                         }
                         Key::Char(ch) => {
-                            app.country_filter.push(ch);
-                            if app.table.selected().is_some() {
-                                app.table.select(None);
-                            }
+                            let filter = format!("{}{}", app.country_filter, ch);
+                            app.search_for(filter);
                         }
                         Key::Ctrl('a') => {
                             app.state.update_focused_widget(Widgets::Mirrors);
                         }
                         _ => {}
                     },
-                    Widgets::Protocols => todo!(),
+                    Widgets::Protocols => match key {
+                        Key::Left | Key::Char('h') => app.protocols.highlight_prev(),
+                        Key::Right | Key::Char('l') => app.protocols.highlight_next(),
+                        Key::Enter | Key::Char(' ') => app.toggle_protocol(),
+                        _ => {}
+                    },
                     Widgets::Mirrors => match key {
                         Key::Enter | Key::Char(' ') => {
                             if let Some(index) = app.table.selected() {
-                                let inner_list: Vec<_> = app
-                                    .mirrors
-                                    .countries
-                                    .iter()
-                                    .filter(|f| {
-                                        f.country
-                                            .to_lowercase()
-                                            .contains(&app.country_filter.to_lowercase())
-                                    })
-                                    .collect();
-                                if let Some(f) = inner_list.get(index) {
-                                    let country = &f.country;
-                                    if !app
-                                        .selected_countries
-                                        .iter()
-                                        .any(|w| w.country.country.eq(country))
-                                    {
-                                        app.selected_countries.push(SelectedCountry {
-                                            country: (*f).clone(),
-                                            search_item: app.country_filter.clone(),
-                                            index: index.try_into().unwrap(),
-                                        });
-                                    }
-                                };
+                                app.activate(ScrollableTables::AllMirrors, index);
                             };
                         }
                         Key::Up | Key::Char('k') => app.scroll_next(ScrollableTables::AllMirrors),
                         Key::Down | Key::Char('j') => app.scroll_prev(ScrollableTables::AllMirrors),
+                        Key::Char('s') => {
+                            let (key, direction) = app.config.sort();
+                            app.change_sort(key.next(), direction);
+                        }
+                        Key::Char('r') => {
+                            let (key, direction) = app.config.sort();
+                            app.change_sort(key, direction.reversed());
+                        }
                         _ => {}
                     },
                     Widgets::SelectedCountries => match key {
                         Key::Enter | Key::Char(' ') => {
                             if let Some(index) = app.selected_table.selected() {
-                                if !app.selected_countries.is_empty() {
-                                    app.selected_countries.remove(index);
-                                    if !app.selected_countries.is_empty() {
-                                        app.selected_table.select(Some(index));
-                                    } else {
-                                        app.selected_table.select(None)
-                                    }
-                                }
+                                app.activate(ScrollableTables::SavedMirrors, index);
                             };
                         }
                         Key::Up | Key::Char('k') => app.scroll_next(ScrollableTables::SavedMirrors),
                         Key::Down | Key::Char('j') => {
                             app.scroll_prev(ScrollableTables::SavedMirrors);
                         }
+                        Key::Char('K') => app.shift_country(true),
+                        Key::Char('J') => app.shift_country(false),
+                        Key::Char('s') => app.sort_selection(),
+                        _ => {}
+                    },
+                    Widgets::CountryMirrors => match key {
+                        Key::Enter | Key::Char(' ') => {
+                            if let Some(index) = app.mirrors_table.selected() {
+                                app.activate(ScrollableTables::FocusedCountry, index);
+                            }
+                        }
+                        Key::Up | Key::Char('k') => {
+                            app.scroll_next(ScrollableTables::FocusedCountry)
+                        }
+                        Key::Down | Key::Char('j') => {
+                            app.scroll_prev(ScrollableTables::FocusedCountry)
+                        }
+                        Key::Char('K') => app.shift_mirror(true),
+                        Key::Char('J') => app.shift_mirror(false),
+                        Key::Char('p') => app.pin_mirror(),
+                        _ => {}
+                    },
+                    // the details follow the cursor of the country's mirrors
+                    Widgets::MirrorDetails => match key {
+                        Key::Enter | Key::Char(' ') => {
+                            if let Some(index) = app.mirrors_table.selected() {
+                                app.activate(ScrollableTables::FocusedCountry, index);
+                            }
+                        }
+                        Key::Up | Key::Char('k') => {
+                            app.scroll_next(ScrollableTables::FocusedCountry)
+                        }
+                        Key::Down | Key::Char('j') => {
+                            app.scroll_prev(ScrollableTables::FocusedCountry)
+                        }
+                        Key::Char('K') => app.shift_mirror(true),
+                        Key::Char('J') => app.shift_mirror(false),
+                        Key::Char('p') => app.pin_mirror(),
+                        Key::Esc => app.state.update_focused_widget(Widgets::CountryMirrors),
                         _ => {}
                     },
                 }
                 AppReturn::Continue
             }
-            Action::SimpleExport => {
-                export::export_mirrors(app.selected_countries.clone(), false).await
-            }
-            Action::RateExport => {
-                export::export_mirrors(app.selected_countries.clone(), true).await
+            Action::SimpleExport | Action::RateExport => {
+                if !app.confirm_export(action) {
+                    return AppReturn::Continue;
+                }
+                let client = match app.export_client() {
+                    Some(client) => client,
+                    None => return AppReturn::Continue,
+                };
+                export::export_mirrors(
+                    app.selected_countries.clone(),
+                    app.protocols.clone(),
+                    action == Action::RateExport,
+                    app.hand_ordered,
+                    client,
+                    app.config.export_path(),
+                    app.config.export_backups(),
+                )
+                .await
             }
         }
     } else {
         AppReturn::Continue
     }
 }
+
+fn mouse_handler(app: &mut App, mouse: Mouse) -> AppReturn {
+    let (column, row) = match mouse.position() {
+        Some(position) => position,
+        None => return AppReturn::Continue,
+    };
+    // nothing can be clicked before the widgets are shown
+    let widget = match (app.state.focused_widget(), app.areas.widget_at(column, row)) {
+        (Some(_), Some(widget)) => widget,
+        _ => return AppReturn::Continue,
+    };
+    app.pending_export = None;
+    app.export_error = None;
+    let table = match widget {
+        Widgets::Mirrors => Some(ScrollableTables::AllMirrors),
+        Widgets::SelectedCountries => Some(ScrollableTables::SavedMirrors),
+        // the details follow the cursor of the country's mirrors
+        Widgets::CountryMirrors | Widgets::MirrorDetails => Some(ScrollableTables::FocusedCountry),
+        Widgets::CountryFilter | Widgets::Protocols => None,
+    };
+    match mouse {
+        Mouse::ScrollUp { .. } => {
+            if let Some(table) = table {
+                app.scroll_next(table);
+            }
+        }
+        Mouse::ScrollDown { .. } => {
+            if let Some(table) = table {
+                app.scroll_prev(table);
+            }
+        }
+        // clicks inside the details keep them open
+        Mouse::Click { .. } if widget == Widgets::MirrorDetails => {}
+        Mouse::Click { .. } => {
+            trace!("{} widget clicked", widget);
+            app.state.update_focused_widget(widget);
+            if let Some(table) = table {
+                app.click_row(table, column, row);
+            } else if widget == Widgets::Protocols {
+                if let Some(index) = app.areas.protocol_at(column, row) {
+                    app.protocols.highlight(index);
+                    app.toggle_protocol();
+                }
+            }
+        }
+        Mouse::Unknown => {}
+    }
+    AppReturn::Continue
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Utc};
+    use linux_mirrors::archlinux::internal::ArchMirrors;
+    use tui::{backend::TestBackend, style::Color, Terminal};
+
+    use super::{
+        actions::Action,
+        config::{MirrorsConfig, Network},
+        export,
+        state::{CountryStats, Provenance, SortDirection, SortKey, Widgets},
+        ui, App, ScrollableTables,
+    };
+    use crate::inputs::mouse::Mouse;
+
+    fn app(provenance: Provenance, age: Option<Duration>) -> App {
+        let (tx, _) = tokio::sync::mpsc::channel(1);
+        let mut app = App::new(tx, MirrorsConfig::default());
+        let mut mirrors: ArchMirrors =
+            serde_json::from_str(include_str!("../../../assets/arch_mirrors.json")).unwrap();
+        if let Some(age) = age {
+            mirrors.last_check = Utc::now() - age;
+        }
+        app.update_mirrors(&mirrors, provenance);
+        app
+    }
+
+    #[test]
+    fn should_pick_mirrors_individually_or_by_country() {
+        let mut app = app(Provenance::Live, None);
+        app.search_for(String::from("germany"));
+        let germany = app.filtered_countries()[0].clone();
+        assert_eq!(germany.country, "Germany");
+
+        // the country picks its active mirrors with a checked protocol
+        app.select_country(0);
+        let picked: Vec<_> = app.selection(&germany).unwrap().picked().collect();
+        assert!(!picked.is_empty());
+        assert!(picked
+            .iter()
+            .all(|f| f.active && app.protocols.allows(&f.protocol)));
+        assert!(germany
+            .mirrors
+            .iter()
+            .any(|f| !app.protocols.allows(&f.protocol)));
+
+        // mirrors are toggled one at a time, the last one unmarks the country
+        app.update_mirrors_widget(ScrollableTables::AllMirrors, 0);
+        let count = app.focused_mirrors().len();
+        for index in 0..count {
+            if app
+                .selection(&germany)
+                .is_some_and(|f| f.is_picked(app.focused_mirrors()[index]))
+            {
+                app.toggle_mirror(index);
+            }
+        }
+        assert!(app.selection(&germany).is_none());
+        app.toggle_mirror(1);
+        let selected = app.selection(&germany).unwrap();
+        assert_eq!(
+            selected.picked().map(|f| &f.url).collect::<Vec<_>>(),
+            [&app.focused_mirrors()[1].url]
+        );
+
+        // marking the country again keeps what was picked by hand
+        app.select_country(0);
+        let selected = app.selection(&germany).unwrap();
+        assert_eq!(
+            selected.picked().map(|f| &f.url).collect::<Vec<_>>(),
+            [&app.focused_mirrors()[1].url]
+        );
+    }
+
+    #[test]
+    fn should_sort_countries_by_the_chosen_column() {
+        let mut app = app(Provenance::Live, None);
+        let names: Vec<_> = app
+            .filtered_countries()
+            .iter()
+            .map(|f| f.country.clone())
+            .collect();
+        let mut sorted = names.clone();
+        sorted.sort();
+        assert_eq!(names, sorted);
+
+        app.change_sort(SortKey::Mirrors, SortDirection::Descending);
+        let counts: Vec<_> = app
+            .filtered_countries()
+            .iter()
+            .map(|f| CountryStats::new(f, &app.protocols).mirrors)
+            .collect();
+        assert!(counts.windows(2).all(|f| f[0] >= f[1]));
+
+        app.change_sort(SortKey::Score, SortDirection::Ascending);
+        let scores: Vec<_> = app
+            .filtered_countries()
+            .iter()
+            .map(|f| CountryStats::new(f, &app.protocols).score)
+            .collect();
+        let known = scores.iter().take_while(|f| f.is_some()).count();
+        assert!(known > 0);
+        assert!(scores[known..].iter().all(Option::is_none));
+        assert!(scores[..known].windows(2).all(|f| f[0] <= f[1]));
+
+        // countries without a score stay last when reversed
+        app.change_sort(SortKey::Score, SortDirection::Descending);
+        let reversed: Vec<_> = app
+            .filtered_countries()
+            .iter()
+            .map(|f| CountryStats::new(f, &app.protocols).score)
+            .collect();
+        assert!(known < reversed.len());
+        assert!(reversed[known..].iter().all(Option::is_none));
+        assert!(reversed[..known].windows(2).all(|f| f[0] >= f[1]));
+    }
+
+    #[test]
+    fn should_rank_countries_by_how_well_they_match() {
+        let mut app = app(Provenance::Live, None);
+        app.search_for(String::from("de"));
+        let countries = app.filtered_countries();
+        assert_eq!(countries[0].country, "Germany");
+        assert!(countries.iter().any(|f| f.country == "Denmark"));
+
+        app.search_for(String::from("/^united"));
+        let countries = app.filtered_countries();
+        assert!(!countries.is_empty());
+        assert!(countries.iter().all(|f| f.country.starts_with("United")));
+
+        app.search_for(String::from("/[a-"));
+        assert!(app.search().is_err());
+        assert!(app.filtered_countries().is_empty());
+    }
+
+    #[test]
+    fn should_pick_mirrors_found_by_hostname() {
+        let mut app = app(Provenance::Live, None);
+        app.search_for(String::from("@kernel.org"));
+        assert!(app.searching_mirrors());
+        assert!(app.filtered_countries().is_empty());
+        let found = app.mirror_matches();
+        assert!(!found.is_empty());
+        assert!(found
+            .iter()
+            .all(|(_, f, _)| app.protocols.allows(&f.protocol)));
+        let (country, mirror, _) = found[0];
+        assert!(mirror.url.contains("kernel.org"));
+        let (country, mirror) = (country.clone(), mirror.clone());
+
+        app.toggle_found_mirror(0);
+        assert_eq!(app.focused_country().country, country.country);
+        assert_eq!(app.highlighted_mirror(), Some(&mirror));
+        let picked: Vec<_> = app.selection(&country).unwrap().picked().collect();
+        assert_eq!(picked, [&mirror]);
+        assert_eq!(
+            export::picked_servers(&app.selected_countries, &app.protocols)
+                .iter()
+                .map(|f| f.url.as_str())
+                .collect::<Vec<_>>(),
+            [mirror.url.as_str()]
+        );
+
+        app.toggle_found_mirror(0);
+        assert!(app.selection(&country).is_none());
+    }
+
+    #[test]
+    fn should_export_in_priority_order() {
+        let mut app = app(Provenance::Live, None);
+        let urls = |app: &App| -> Vec<String> {
+            export::picked_servers(&app.selected_countries, &app.protocols)
+                .into_iter()
+                .map(|f| f.url)
+                .collect()
+        };
+        for country in ["germany", "france", "sweden"] {
+            app.search_for(String::from(country));
+            app.select_country(0);
+        }
+        let countries = |app: &App| -> Vec<String> {
+            app.selected_countries
+                .iter()
+                .map(|f| f.country.country.clone())
+                .collect()
+        };
+        assert_eq!(countries(&app), ["Germany", "France", "Sweden"]);
+
+        // countries move with the cursor, and stop at either end
+        assert!(!app.hand_ordered);
+        app.selected_table.select(Some(2));
+        app.shift_country(true);
+        // rated exports now keep this order
+        assert!(app.hand_ordered);
+        assert_eq!(countries(&app), ["Germany", "Sweden", "France"]);
+        assert_eq!(app.selected_table.selected(), Some(1));
+        app.shift_country(true);
+        app.shift_country(true);
+        assert_eq!(countries(&app), ["Sweden", "Germany", "France"]);
+        assert_eq!(app.selected_table.selected(), Some(0));
+
+        // mirrors move within their country
+        app.update_mirrors_widget(ScrollableTables::SavedMirrors, 2);
+        assert_eq!(app.focused_country().country, "France");
+        let france = app
+            .selection(app.focused_country())
+            .unwrap()
+            .mirrors
+            .clone();
+        let last = app
+            .focused_mirrors()
+            .iter()
+            .position(|f| f.url == france[france.len() - 1])
+            .unwrap();
+        app.mirrors_table.select(Some(last));
+        app.shift_mirror(true);
+        let moved = &app.selection(app.focused_country()).unwrap().mirrors;
+        assert_eq!(moved[france.len() - 2], france[france.len() - 1]);
+
+        // a pinned mirror is exported first, picked or not
+        let unpicked = app
+            .focused_mirrors()
+            .iter()
+            .position(|f| f.url == france[0])
+            .unwrap();
+        app.toggle_mirror(unpicked);
+        let pinned = app.focused_mirrors()[unpicked].url.clone();
+        app.mirrors_table.select(Some(unpicked));
+        app.pin_mirror();
+        assert_eq!(countries(&app), ["France", "Sweden", "Germany"]);
+        assert_eq!(urls(&app)[0], pinned);
+        assert_eq!(urls(&app).len(), {
+            let picked: usize = app.selected_countries.iter().map(|f| f.mirrors.len()).sum();
+            picked
+        });
+
+        // sorting orders the countries and the mirrors of each
+        app.change_sort(SortKey::Country, SortDirection::Descending);
+        app.sort_selection();
+        assert_eq!(countries(&app), ["Sweden", "Germany", "France"]);
+        app.change_sort(SortKey::Score, SortDirection::Ascending);
+        app.sort_selection();
+        for selected in app.selected_countries.iter() {
+            let scores: Vec<_> = selected
+                .picked()
+                .map(|f| f.score.unwrap_or(f64::MAX))
+                .collect();
+            assert!(scores.windows(2).all(|f| f[0] <= f[1]));
+        }
+
+        // a mirror without a score stays last when reversed
+        let unscored = app.selected_countries[0].mirrors[0].clone();
+        for mirror in app.selected_countries[0].country.mirrors.iter_mut() {
+            if mirror.url == unscored {
+                mirror.score = None;
+            }
+        }
+        app.change_sort(SortKey::Score, SortDirection::Descending);
+        app.sort_selection();
+        let scores: Vec<_> = app.selected_countries[0]
+            .picked()
+            .map(|f| f.score)
+            .collect();
+        let known = scores.iter().take_while(|f| f.is_some()).count();
+        assert!(known > 1);
+        assert!(scores[known..].contains(&None));
+        assert!(scores[known..].iter().all(Option::is_none));
+        assert!(scores[..known].windows(2).all(|f| f[0] >= f[1]));
+    }
+
+    #[tokio::test]
+    async fn should_focus_and_select_what_is_clicked() {
+        let mut app = app(Provenance::Live, None);
+        let mut terminal = Terminal::new(TestBackend::new(160, 50)).unwrap();
+        let click = |(column, row)| Mouse::Click { column, row };
+
+        // nothing happens before the widgets are shown
+        terminal.draw(|f| ui::draw(f, &mut app)).unwrap();
+        app.do_mouse(click((1, 1))).await;
+        assert_eq!(app.state.focused_widget(), None);
+
+        app.initialized();
+        terminal.draw(|f| ui::draw(f, &mut app)).unwrap();
+        let third = app.filtered_countries()[2].clone();
+        let position = find(&terminal, &third.country);
+        app.do_mouse(click(position)).await;
+        assert_eq!(app.state.focused_widget(), Some(&Widgets::Mirrors));
+        assert_eq!(app.table.selected(), Some(2));
+        assert_eq!(app.focused_country().country, third.country);
+        assert!(app.selection(&third).is_none());
+
+        // clicking the row under the cursor marks it
+        app.do_mouse(click(position)).await;
+        assert!(app.selection(&third).is_some());
+
+        let (column, row) = position;
+        app.do_mouse(Mouse::ScrollDown { column, row }).await;
+        assert_eq!(app.table.selected(), Some(3));
+        app.do_mouse(Mouse::ScrollUp { column, row }).await;
+        app.do_mouse(Mouse::ScrollUp { column, row }).await;
+        assert_eq!(app.table.selected(), Some(1));
+
+        terminal.draw(|f| ui::draw(f, &mut app)).unwrap();
+        assert!(!app.protocols.rsync);
+        app.do_mouse(click(find(&terminal, "rsync"))).await;
+        assert_eq!(app.state.focused_widget(), Some(&Widgets::Protocols));
+        assert!(app.protocols.rsync);
+    }
+
+    #[test]
+    fn should_keep_the_cursor_on_a_row_when_removing_countries() {
+        let mut app = app(Provenance::Live, None);
+        for country in ["germany", "france"] {
+            app.search_for(String::from(country));
+            app.select_country(0);
+        }
+
+        // removing the last row moves the cursor up onto the new last row
+        app.activate(ScrollableTables::SavedMirrors, 1);
+        assert_eq!(app.selected_countries.len(), 1);
+        assert_eq!(app.selected_table.selected(), Some(0));
+        app.activate(ScrollableTables::SavedMirrors, 0);
+        assert!(app.selected_countries.is_empty());
+        assert_eq!(app.selected_table.selected(), None);
+    }
+
+    #[test]
+    fn should_show_notes_apart_from_errors() {
+        let mut app = app(Provenance::Cached, None);
+        app.initialized();
+        app.fetch_error = Some("offline".to_owned());
+        app.fetch_note = Some("1 malformed mirror was left out".to_owned());
+        let mut terminal = Terminal::new(TestBackend::new(160, 50)).unwrap();
+        terminal.draw(|f| ui::draw(f, &mut app)).unwrap();
+        let (column, row) = find(&terminal, "offline");
+        let note = find(&terminal, "1 malformed mirror was left out");
+        assert_eq!(note.1, row);
+
+        let buffer = terminal.backend().buffer();
+        assert_eq!(buffer.get(column, row).fg, Color::Yellow);
+        assert_eq!(buffer.get(note.0, note.1).fg, Color::Reset);
+    }
+
+    /// Where `text` is first drawn
+    fn find(terminal: &Terminal<TestBackend>, text: &str) -> (u16, u16) {
+        let buffer = terminal.backend().buffer();
+        let area = buffer.area();
+        for row in area.top()..area.bottom() {
+            let line: String = (area.left()..area.right())
+                .map(|column| buffer.get(column, row).symbol.clone())
+                .collect();
+            if let Some(column) = line.find(text) {
+                let column = line[..column].chars().count() as u16;
+                return (column, row);
+            }
+        }
+        panic!("{} is not drawn", text)
+    }
+
+    #[test]
+    fn should_list_nothing_without_protocols() {
+        let mut app = app(Provenance::Live, None);
+        assert!(!app.filtered_countries().is_empty());
+        app.protocols.https = false;
+        app.protocols.http = false;
+        app.protocols.rsync = false;
+        assert!(app.filtered_countries().is_empty());
+        app.search_for(String::from("@kernel"));
+        assert!(app.mirror_matches().is_empty());
+    }
+
+    #[test]
+    fn should_flag_old_data_that_was_not_fetched_now() {
+        assert!(app(Provenance::Bundled, None).is_stale());
+        assert!(app(Provenance::Cached, Some(Duration::days(3))).is_stale());
+        assert!(!app(Provenance::Cached, Some(Duration::hours(1))).is_stale());
+        assert!(!app(Provenance::Live, None).is_stale());
+    }
+
+    #[test]
+    fn should_confirm_exports_of_stale_data() {
+        let mut bundled = app(Provenance::Bundled, None);
+        assert!(!bundled.confirm_export(Action::RateExport));
+        assert_eq!(bundled.pending_export(), Some(Action::RateExport));
+        assert!(!bundled.confirm_export(Action::SimpleExport));
+        assert!(bundled.confirm_export(Action::SimpleExport));
+        assert_eq!(bundled.pending_export(), None);
+        assert!(!bundled.confirm_export(Action::SimpleExport));
+
+        let mut live = app(Provenance::Live, None);
+        assert!(live.confirm_export(Action::RateExport));
+    }
+
+    #[test]
+    fn should_not_export_with_an_invalid_network_config() {
+        let mut app = app(Provenance::Live, None);
+        assert!(app.export_client().is_some());
+        assert_eq!(app.export_error(), None);
+
+        app.config.network = Some(Network {
+            client_cert: Some("client.pem".to_owned()),
+            ..Default::default()
+        });
+        assert!(app.export_client().is_none());
+        assert!(app.export_error().unwrap().contains("client-key"));
+    }
+}
@@ -0,0 +1,207 @@
+use fuzzy_matcher::{skim::SkimMatcherV2, FuzzyMatcher};
+use linux_mirrors::archlinux::internal::{Mirror, Url};
+use regex::{Regex, RegexBuilder};
+
+/// Filters starting with this are regular expressions
+pub const REGEX_PREFIX: char = '/';
+/// Filters starting with this search the hostnames of the mirrors of every country
+pub const MIRROR_PREFIX: char = '@';
+
+/// How the country filter is matched against countries, or mirrors
+pub enum Search {
+    /// An empty filter, matching every country
+    All,
+    /// Names containing the characters of the filter in order, or the country with the filter
+    /// as its code, ignoring case
+    Fuzzy(String, Box<SkimMatcherV2>),
+    /// Names or codes matching the expression, ignoring case
+    Regex(Regex),
+    /// Mirrors whose hostname contains the characters of the filter in order, ignoring case.
+    /// Matches no country
+    Mirrors(String, Box<SkimMatcherV2>),
+}
+
+/// How well a country, or mirror, matched the filter
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Match {
+    /// Higher is better
+    pub score: i64,
+    /// Positions of the matched characters (not bytes) of the country name, or mirror hostname
+    pub indices: Vec<usize>,
+}
+
+impl Search {
+    /// Fails when the filter is an invalid regular expression
+    pub fn new(filter: &str) -> Result<Self, regex::Error> {
+        if let Some(host) = filter.strip_prefix(MIRROR_PREFIX) {
+            return Ok(Search::Mirrors(
+                host.trim().to_owned(),
+                Box::new(SkimMatcherV2::default().ignore_case()),
+            ));
+        }
+        match filter.strip_prefix(REGEX_PREFIX) {
+            Some(pattern) => RegexBuilder::new(pattern)
+                .case_insensitive(true)
+                .build()
+                .map(Search::Regex),
+            None if filter.trim().is_empty() => Ok(Search::All),
+            None => Ok(Search::Fuzzy(
+                filter.trim().to_owned(),
+                Box::new(SkimMatcherV2::default().ignore_case()),
+            )),
+        }
+    }
+
+    pub fn is_regex(&self) -> bool {
+        matches!(self, Search::Regex(_))
+    }
+
+    /// Whether mirrors are searched instead of countries
+    pub fn is_mirrors(&self) -> bool {
+        matches!(self, Search::Mirrors(..))
+    }
+
+    /// How well the hostname of `mirror` matches, only mirrors are matched by [`Search::Mirrors`]
+    pub fn matches_mirror(&self, mirror: &Mirror) -> Option<Match> {
+        match self {
+            Search::Mirrors(pattern, _) if pattern.is_empty() => Some(Match::default()),
+            Search::Mirrors(pattern, matcher) => matcher
+                .fuzzy_indices(hostname(&mirror.url), pattern)
+                .map(|(score, indices)| Match { score, indices }),
+            _ => None,
+        }
+    }
+
+    pub fn matches(&self, country: &Url) -> Option<Match> {
+        match self {
+            Search::All => Some(Match::default()),
+            Search::Mirrors(..) => None,
+            Search::Fuzzy(pattern, matcher) => {
+                // the country with the filter as its code goes before any matched name
+                if pattern.eq_ignore_ascii_case(&country.country_code) {
+                    return Some(Match {
+                        score: i64::MAX,
+                        indices: vec![],
+                    });
+                }
+                matcher
+                    .fuzzy_indices(&country.country, pattern)
+                    .map(|(score, indices)| Match { score, indices })
+            }
+            Search::Regex(regex) => match regex.find(&country.country) {
+                Some(found) => {
+                    let start = country.country[..found.start()].chars().count();
+                    let len = found.as_str().chars().count();
+                    Some(Match {
+                        score: 0,
+                        indices: (start..start + len).collect(),
+                    })
+                }
+                None if regex.is_match(&country.country_code) => Some(Match::default()),
+                None => None,
+            },
+        }
+    }
+}
+
+/// The host of `url`, without its scheme, credentials, port or path
+pub fn hostname(url: &str) -> &str {
+    let url = url.split_once("://").map_or(url, |(_, rest)| rest);
+    let authority = url.split('/').next().unwrap_or_default();
+    let host = authority
+        .rsplit_once('@')
+        .map_or(authority, |(_, host)| host);
+    match host.rsplit_once(':') {
+        Some((name, port)) if port.chars().all(|f| f.is_ascii_digit()) => name,
+        _ => host,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use linux_mirrors::archlinux::internal::{Mirror, Url};
+
+    use super::{hostname, Search};
+
+    fn country(name: &str, code: &str) -> Url {
+        Url {
+            country: name.to_owned(),
+            country_code: code.to_owned(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn should_rank_fuzzy_matches() {
+        let search = Search::new("de").unwrap();
+        let germany = search.matches(&country("Germany", "DE")).unwrap();
+        let denmark = search.matches(&country("Denmark", "DK")).unwrap();
+        let sweden = search.matches(&country("Sweden", "SE")).unwrap();
+        assert!(germany.score > denmark.score);
+        assert!(germany.indices.is_empty());
+        assert_eq!(denmark.indices, [0, 1]);
+        assert_eq!(sweden.indices, [3, 4]);
+
+        let search = Search::new("nzl").unwrap();
+        let zealand = search.matches(&country("New Zealand", "NZ")).unwrap();
+        assert_eq!(zealand.indices, [0, 4, 7]);
+        assert!(search.matches(&country("Norway", "NO")).is_none());
+    }
+
+    #[test]
+    fn should_match_regular_expressions() {
+        let search = Search::new("/^s.*n$").unwrap();
+        assert!(search.is_regex());
+        assert_eq!(
+            search.matches(&country("Sweden", "SE")).unwrap().indices,
+            [0, 1, 2, 3, 4, 5]
+        );
+        assert!(search.matches(&country("Spain", "ES")).is_some());
+        assert!(search.matches(&country("Serbia", "RS")).is_none());
+
+        let search = Search::new("/ü").unwrap();
+        assert_eq!(
+            search.matches(&country("Türkiye", "TR")).unwrap().indices,
+            [1]
+        );
+        assert!(Search::new("/^D[EK]$")
+            .unwrap()
+            .matches(&country("Germany", "DE"))
+            .is_some());
+        assert!(Search::new("/(").is_err());
+    }
+
+    #[test]
+    fn should_search_mirror_hostnames() {
+        assert_eq!(
+            hostname("https://mirrors.kernel.org/archlinux/"),
+            "mirrors.kernel.org"
+        );
+        assert_eq!(
+            hostname("rsync://user@mirror.example:873/arch"),
+            "mirror.example"
+        );
+        assert_eq!(hostname("ftp.example.org"), "ftp.example.org");
+
+        let mirror = |url: &str| {
+            let mut mirror = Mirror::default();
+            mirror.url = url.to_owned();
+            mirror
+        };
+        let search = Search::new("@kernel").unwrap();
+        assert!(search.is_mirrors());
+        assert!(search.matches(&country("Germany", "DE")).is_none());
+        let kernel = search
+            .matches_mirror(&mirror("https://mirrors.kernel.org/archlinux/"))
+            .unwrap();
+        assert_eq!(kernel.indices, [8, 9, 10, 11, 12, 13]);
+        // only the hostname is searched
+        assert!(search
+            .matches_mirror(&mirror("https://example.org/kernel/"))
+            .is_none());
+        assert!(Search::new("kernel")
+            .unwrap()
+            .matches_mirror(&mirror("https://mirrors.kernel.org/"))
+            .is_none());
+    }
+}
@@ -1,9 +1,13 @@
+use std::cmp::Ordering;
+use std::fmt::{self, Display};
 use std::time::Duration;
 
-use linux_mirrors::archlinux::internal::Url;
+use linux_mirrors::archlinux::internal::{Mirror, Protocol, Url};
+use serde::{Deserialize, Serialize};
 
-#[derive(Clone)]
+#[derive(Clone, Default)]
 pub enum AppState {
+    #[default]
     Init,
     Initialized {
         duration: Duration,
@@ -19,17 +23,21 @@ pub enum Widgets {
     Protocols,
     Mirrors,
     SelectedCountries,
+    CountryMirrors,
+    MirrorDetails,
 }
 
-impl ToString for Widgets {
-    fn to_string(&self) -> String {
-        match &self {
+impl Display for Widgets {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let str = match &self {
             Widgets::CountryFilter => "filter",
             Widgets::Protocols => "protocols",
             Widgets::Mirrors => "mirrorlist",
             Widgets::SelectedCountries => "marked mirrors",
-        }
-        .to_owned()
+            Widgets::CountryMirrors => "country mirrors",
+            Widgets::MirrorDetails => "mirror details",
+        };
+        write!(f, "{}", str)
     }
 }
 
@@ -110,15 +118,342 @@ impl AppState {
     }
 }
 
-impl Default for AppState {
-    fn default() -> Self {
-        Self::Init
+/// Where the mirror status on screen was loaded from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Provenance {
+    /// Downloaded, or confirmed unchanged, on this launch
+    Live,
+    /// The last status that was downloaded, the server could not be reached
+    Cached,
+    /// The snapshot shipped with the binary
+    #[default]
+    Bundled,
+}
+
+impl Display for Provenance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let str = match &self {
+            Provenance::Live => "live",
+            Provenance::Cached => "cached",
+            Provenance::Bundled => "bundled",
+        };
+        write!(f, "{}", str)
     }
 }
 
+/// A country marked for saving, with the mirrors of it that are picked
 #[derive(Debug, Clone)]
 pub struct SelectedCountry {
     pub country: Url,
     pub search_item: String,
     pub index: u8,
+    /// Urls of the picked mirrors, in the order they are exported
+    pub mirrors: Vec<String>,
+}
+
+impl SelectedCountry {
+    /// Marks `country` with nothing picked yet
+    pub fn new(country: Url, search_item: String, index: u8) -> Self {
+        Self {
+            country,
+            search_item,
+            index,
+            mirrors: vec![],
+        }
+    }
+
+    pub fn is_picked(&self, mirror: &Mirror) -> bool {
+        self.mirrors.contains(&mirror.url)
+    }
+
+    /// Where `mirror` is exported among the picked mirrors of the country, from 0
+    pub fn rank(&self, mirror: &Mirror) -> Option<usize> {
+        self.mirrors.iter().position(|f| *f == mirror.url)
+    }
+
+    /// Picks `mirror`, last, if it is not picked, and the other way round
+    pub fn toggle(&mut self, mirror: &Mirror) {
+        match self.rank(mirror) {
+            Some(rank) => {
+                self.mirrors.remove(rank);
+            }
+            None => self.mirrors.push(mirror.url.clone()),
+        }
+    }
+
+    /// Exports a picked `mirror` one place earlier, or later
+    pub fn shift(&mut self, mirror: &Mirror, up: bool) {
+        if let Some(rank) = self.rank(mirror) {
+            shift(&mut self.mirrors, rank, up);
+        }
+    }
+
+    /// Exports `mirror` first among the mirrors of the country, picking it if needed
+    pub fn pin(&mut self, mirror: &Mirror) {
+        if let Some(rank) = self.rank(mirror) {
+            self.mirrors.remove(rank);
+        }
+        self.mirrors.insert(0, mirror.url.clone());
+    }
+
+    /// Orders the picked mirrors by `key`, when it applies to single mirrors
+    pub fn sort(&mut self, key: SortKey, direction: SortDirection) {
+        let mut mirrors: Vec<_> = self.picked().cloned().collect();
+        mirrors.sort_by(|a, b| match key {
+            SortKey::Score => known_first(a.score, b.score, direction, f64::total_cmp),
+            SortKey::Delay => known_first(a.delay, b.delay, direction, Ord::cmp),
+            SortKey::Active => direction.apply(a.active.cmp(&b.active)),
+            SortKey::Https => direction
+                .apply((a.protocol == Protocol::Https).cmp(&(b.protocol == Protocol::Https))),
+            SortKey::Country | SortKey::Mirrors => Ordering::Equal,
+        });
+        self.mirrors = mirrors.into_iter().map(|f| f.url).collect();
+    }
+
+    /// The picked mirrors, in the order they are exported
+    pub fn picked(&self) -> impl Iterator<Item = &Mirror> {
+        self.mirrors
+            .iter()
+            .filter_map(|url| self.country.mirrors.iter().find(|f| f.url == *url))
+    }
+}
+
+/// Swaps `items[index]` with the item before, or after, it. Returns where it ended up
+pub fn shift<T>(items: &mut [T], index: usize, up: bool) -> usize {
+    let to = match up {
+        true => index.saturating_sub(1),
+        false => (index + 1).min(items.len().saturating_sub(1)),
+    };
+    if index < items.len() {
+        items.swap(index, to);
+    }
+    to
+}
+
+/// The protocol checkboxes, `highlighted` is the box the cursor is on
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Protocols {
+    pub https: bool,
+    pub http: bool,
+    pub rsync: bool,
+    highlighted: usize,
+}
+
+impl Default for Protocols {
+    fn default() -> Self {
+        Self {
+            https: true,
+            http: true,
+            rsync: false,
+            highlighted: 0,
+        }
+    }
+}
+
+impl Protocols {
+    const COUNT: usize = 3;
+
+    /// Whether mirrors served over `protocol` should be shown and exported
+    pub fn allows(&self, protocol: &Protocol) -> bool {
+        match protocol {
+            Protocol::Https => self.https,
+            Protocol::Http => self.http,
+            Protocol::Rsync => self.rsync,
+            Protocol::Ftp | Protocol::Other(_) => false,
+        }
+    }
+
+    /// The checkboxes in the order they are drawn
+    pub fn checkboxes(&self) -> [(Protocol, bool); Self::COUNT] {
+        [
+            (Protocol::Https, self.https),
+            (Protocol::Http, self.http),
+            (Protocol::Rsync, self.rsync),
+        ]
+    }
+
+    pub fn highlighted(&self) -> usize {
+        self.highlighted
+    }
+
+    pub fn highlight_next(&mut self) {
+        self.highlighted = (self.highlighted + 1) % Self::COUNT;
+    }
+
+    pub fn highlight_prev(&mut self) {
+        self.highlighted = (self.highlighted + Self::COUNT - 1) % Self::COUNT;
+    }
+
+    /// Moves the cursor to the `index`th checkbox
+    pub fn highlight(&mut self, index: usize) {
+        self.highlighted = index.min(Self::COUNT - 1);
+    }
+
+    pub fn toggle_highlighted(&mut self) {
+        match self.highlighted {
+            0 => self.https = !self.https,
+            1 => self.http = !self.http,
+            _ => self.rsync = !self.rsync,
+        }
+    }
+
+    /// The checked protocols
+    pub fn enabled(&self) -> Vec<Protocol> {
+        self.checkboxes()
+            .into_iter()
+            .filter(|(_, checked)| *checked)
+            .map(|(protocol, _)| protocol)
+            .collect()
+    }
+}
+
+/// The column the available mirrors table is sorted by
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortKey {
+    #[default]
+    Country,
+    Mirrors,
+    Score,
+    Delay,
+    Active,
+    Https,
+}
+
+impl SortKey {
+    const ALL: [SortKey; 6] = [
+        SortKey::Country,
+        SortKey::Mirrors,
+        SortKey::Score,
+        SortKey::Delay,
+        SortKey::Active,
+        SortKey::Https,
+    ];
+
+    /// The column after this one, wrapping around
+    pub fn next(self) -> Self {
+        let index = Self::ALL
+            .iter()
+            .position(|f| *f == self)
+            .unwrap_or_default();
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+
+    /// The name used in the config
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SortKey::Country => "country",
+            SortKey::Mirrors => "mirrors",
+            SortKey::Score => "score",
+            SortKey::Delay => "delay",
+            SortKey::Active => "active",
+            SortKey::Https => "https",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortDirection {
+    #[default]
+    Ascending,
+    Descending,
+}
+
+impl SortDirection {
+    pub fn reversed(self) -> Self {
+        match self {
+            SortDirection::Ascending => SortDirection::Descending,
+            SortDirection::Descending => SortDirection::Ascending,
+        }
+    }
+
+    /// `ordering` of an ascending sort, in this direction
+    pub fn apply(self, ordering: Ordering) -> Ordering {
+        match self {
+            SortDirection::Ascending => ordering,
+            SortDirection::Descending => ordering.reverse(),
+        }
+    }
+
+    /// The name used in the config
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SortDirection::Ascending => "ascending",
+            SortDirection::Descending => "descending",
+        }
+    }
+}
+
+/// What the available mirrors table shows of a country, over its mirrors with a checked
+/// protocol
+#[derive(Debug, Clone, PartialEq)]
+pub struct CountryStats {
+    pub mirrors: usize,
+    /// Mean score of the mirrors that have one, lower is better
+    pub score: Option<f64>,
+    /// Delay of the most up to date mirror
+    pub delay: Option<chrono::Duration>,
+    pub active: usize,
+    /// Share of the mirrors served over https, between 0 and 1
+    pub https: f64,
+}
+
+impl CountryStats {
+    pub fn new(country: &Url, protocols: &Protocols) -> Self {
+        let mirrors: Vec<_> = country
+            .mirrors
+            .iter()
+            .filter(|f| protocols.allows(&f.protocol))
+            .collect();
+        let scores: Vec<_> = mirrors.iter().filter_map(|f| f.score).collect();
+        let score = match scores.len() {
+            0 => None,
+            len => Some(scores.iter().sum::<f64>() / len as f64),
+        };
+        let https = match mirrors.len() {
+            0 => 0.0,
+            len => {
+                let https = mirrors
+                    .iter()
+                    .filter(|f| f.protocol == Protocol::Https)
+                    .count();
+                https as f64 / len as f64
+            }
+        };
+        Self {
+            mirrors: mirrors.len(),
+            score,
+            delay: mirrors.iter().filter_map(|f| f.delay).min(),
+            active: mirrors.iter().filter(|f| f.active).count(),
+            https,
+        }
+    }
+
+    /// Orders by `key` in `direction`, countries without a score or delay come last either way
+    pub fn compare(&self, other: &Self, key: SortKey, direction: SortDirection) -> Ordering {
+        match key {
+            SortKey::Country => Ordering::Equal,
+            SortKey::Mirrors => direction.apply(self.mirrors.cmp(&other.mirrors)),
+            SortKey::Score => known_first(self.score, other.score, direction, f64::total_cmp),
+            SortKey::Delay => known_first(self.delay, other.delay, direction, Ord::cmp),
+            SortKey::Active => direction.apply(self.active.cmp(&other.active)),
+            SortKey::Https => direction.apply(self.https.total_cmp(&other.https)),
+        }
+    }
+}
+
+/// Orders known values by `compare` in `direction`, after which come the unknown ones
+pub fn known_first<T>(
+    a: Option<T>,
+    b: Option<T>,
+    direction: SortDirection,
+    compare: impl Fn(&T, &T) -> Ordering,
+) -> Ordering {
+    match (a, b) {
+        (Some(a), Some(b)) => direction.apply(compare(&a, &b)),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    }
 }
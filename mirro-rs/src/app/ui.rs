@@ -1,25 +1,28 @@
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Duration, Local, Utc};
 use clap::crate_name;
+use linux_mirrors::archlinux::internal::Mirror;
 use tracing::error;
 use tui::{
     backend::Backend,
-    layout::{Constraint, Direction, Layout},
+    layout::{Constraint, Direction, Layout, Rect},
     style::{Color, Modifier, Style},
     text::{Span, Spans, Text},
-    widgets::{Block, Borders, Cell, Gauge, Paragraph, Row, Table},
+    widgets::{Block, Borders, Cell, Clear, Gauge, Paragraph, Row, Table},
     Frame,
 };
 use unicode_width::UnicodeWidthStr;
 
 use super::{
     config::{AvailableMirrors, Colours, Countries, Info},
-    state::Widgets,
-    App,
+    search::hostname,
+    state::{CountryStats, SortDirection, SortKey, Widgets},
+    App, ScrollableTables,
 };
 
 pub fn draw(rect: &mut Frame<impl Backend>, app: &mut App) {
     let size = rect.size();
     check_size(&size);
+    app.areas.clear();
     let chunks = Layout::default()
         .direction(Direction::Vertical)
         .constraints(
@@ -67,23 +70,30 @@ pub fn draw(rect: &mut Frame<impl Backend>, app: &mut App) {
                     .as_ref(),
                 )
                 .split(chunks[0]);
-            let input = Paragraph::new(app.country_filter.as_ref()).block(
-                Block::default()
-                    .borders(Borders::ALL)
-                    .title(Spans::from(vec![
-                        Span::styled(
-                            "f".to_string(),
-                            Style::default()
-                                .fg(action_key_colour(&app.config.colours))
-                                .add_modifier(Modifier::BOLD),
-                        ),
-                        Span::styled(
-                            "ilter".to_string(),
-                            Style::default().add_modifier(Modifier::BOLD),
-                        ),
-                    ])),
-            );
+            let mut title = vec![
+                Span::styled(
+                    "f".to_string(),
+                    Style::default()
+                        .fg(action_key_colour(&app.config.colours))
+                        .add_modifier(Modifier::BOLD),
+                ),
+                Span::styled(
+                    "ilter".to_string(),
+                    Style::default().add_modifier(Modifier::BOLD),
+                ),
+            ];
+            match app.search() {
+                Ok(search) if search.is_regex() => title.push(Span::raw(" (regex)")),
+                Ok(_) => {}
+                Err(_) => title.push(Span::styled(
+                    " (invalid regex)",
+                    Style::default().fg(Color::Red),
+                )),
+            }
+            let input = Paragraph::new(app.country_filter.as_ref())
+                .block(Block::default().borders(Borders::ALL).title(title));
             rect.render_widget(input, chunks[1]);
+            app.areas.widget(Widgets::CountryFilter, chunks[1]);
             if let Some(widget) = app.state.focused_widget() {
                 if widget == &Widgets::CountryFilter {
                     rect.set_cursor(
@@ -108,19 +118,54 @@ pub fn draw(rect: &mut Frame<impl Backend>, app: &mut App) {
             ];
             let help = Paragraph::new(Text::from(Spans::from(help)));
             rect.render_widget(help, chunks[0]);
-            let help = vec![
-                Span::raw("Use "),
-                Span::styled("<ctrl +", Style::default().add_modifier(Modifier::BOLD)),
-                Span::styled(
-                    " r ",
+            let help = match (app.export_error(), app.pending_export()) {
+                (Some(error), _) => vec![Span::styled(
+                    error.to_owned(),
                     Style::default()
                         .add_modifier(Modifier::BOLD)
-                        .add_modifier(Modifier::ITALIC)
-                        .fg(Color::Yellow),
-                ),
-                Span::styled(">", Style::default().add_modifier(Modifier::BOLD)),
-                Span::raw(" to rate and export mirrors"),
-            ];
+                        .fg(match &app.config.colours {
+                            Some(colors) => match &colors.info {
+                                Some(available) => stale_header(available),
+                                None => Color::Yellow,
+                            },
+                            None => Color::Yellow,
+                        }),
+                )],
+                (None, Some(action)) => vec![Span::styled(
+                    format!(
+                        "Mirror data is {} old, press {} again to export anyway",
+                        relative_age(app.clock.with_timezone(&Utc), app.mirrors.last_check)
+                            .trim_end_matches(" ago"),
+                        action
+                            .keys()
+                            .first()
+                            .map(|f| f.to_string())
+                            .unwrap_or_default()
+                    ),
+                    Style::default()
+                        .add_modifier(Modifier::BOLD)
+                        .fg(match &app.config.colours {
+                            Some(colors) => match &colors.info {
+                                Some(available) => stale_header(available),
+                                None => Color::Yellow,
+                            },
+                            None => Color::Yellow,
+                        }),
+                )],
+                (None, None) => vec![
+                    Span::raw("Use "),
+                    Span::styled("<ctrl +", Style::default().add_modifier(Modifier::BOLD)),
+                    Span::styled(
+                        " e ",
+                        Style::default()
+                            .add_modifier(Modifier::BOLD)
+                            .add_modifier(Modifier::ITALIC)
+                            .fg(Color::Yellow),
+                    ),
+                    Span::styled(">", Style::default().add_modifier(Modifier::BOLD)),
+                    Span::raw(" to rate and export mirrors"),
+                ],
+            };
             let help = Paragraph::new(Text::from(Spans::from(help)));
             rect.render_widget(help, chunks[2]);
         }
@@ -151,6 +196,7 @@ pub fn draw(rect: &mut Frame<impl Backend>, app: &mut App) {
                 .title(title)
                 .style(Style::default());
             rect.render_widget(input, chunks[1]);
+            app.areas.widget(Widgets::Protocols, chunks[1]);
             {
                 let chunks = Layout::default()
                     .direction(Direction::Horizontal)
@@ -164,48 +210,28 @@ pub fn draw(rect: &mut Frame<impl Backend>, app: &mut App) {
                         .as_ref(),
                     )
                     .split(chunks[1]);
-                let title = vec![
-                    Span::styled(
-                        "h",
-                        Style::default()
-                            .fg(action_key_colour(&app.config.colours))
-                            .add_modifier(Modifier::BOLD),
-                    ),
-                    Span::styled("ttps", Style::default().add_modifier(Modifier::BOLD)),
-                ];
-                let https = Block::default()
-                    .borders(Borders::ALL)
-                    .title(title)
-                    .title_alignment(tui::layout::Alignment::Center);
-                let title = vec![
-                    Span::styled(
-                        "h",
-                        Style::default()
-                            .fg(action_key_colour(&app.config.colours))
-                            .add_modifier(Modifier::BOLD),
-                    ),
-                    Span::styled("ttp", Style::default().add_modifier(Modifier::BOLD)),
-                ];
-                let http = Block::default()
-                    .borders(Borders::ALL)
-                    .title(title)
-                    .title_alignment(tui::layout::Alignment::Center);
-                let title = vec![
-                    Span::styled(
-                        "r",
+                let focused = app.state.focused_widget() == Some(&Widgets::Protocols);
+                for (index, (protocol, checked)) in app.protocols.checkboxes().iter().enumerate() {
+                    let style = if focused && index == app.protocols.highlighted() {
                         Style::default()
                             .fg(action_key_colour(&app.config.colours))
-                            .add_modifier(Modifier::BOLD),
-                    ),
-                    Span::styled("sync", Style::default().add_modifier(Modifier::BOLD)),
-                ];
-                let rsync = Block::default()
-                    .borders(Borders::ALL)
-                    .title(title)
-                    .title_alignment(tui::layout::Alignment::Center);
-                rect.render_widget(https, chunks[0]);
-                rect.render_widget(http, chunks[1]);
-                rect.render_widget(rsync, chunks[2]);
+                            .add_modifier(Modifier::BOLD)
+                    } else {
+                        Style::default().add_modifier(Modifier::BOLD)
+                    };
+                    let checkbox =
+                        Paragraph::new(Span::styled(if *checked { "[x]" } else { "[ ]" }, style))
+                            .alignment(tui::layout::Alignment::Center)
+                            .block(
+                                Block::default()
+                                    .borders(Borders::ALL)
+                                    .border_style(style)
+                                    .title(Span::styled(protocol.to_string(), style))
+                                    .title_alignment(tui::layout::Alignment::Center),
+                            );
+                    rect.render_widget(checkbox, chunks[index]);
+                    app.areas.protocol(chunks[index]);
+                }
             }
         }
         {
@@ -226,8 +252,8 @@ pub fn draw(rect: &mut Frame<impl Backend>, app: &mut App) {
                 count += f.mirrors.len();
             });
             let os = get_os_name();
-            let datetime = DateTime::parse_from_rfc3339("2022-02-13T12:08:04.349Z").unwrap();
-            let datetime_utc = datetime.with_timezone(&Utc);
+            let last_check = app.mirrors.last_check.with_timezone(&Local);
+            let stale = app.is_stale();
 
             let rows = vec![
                 Row::new(vec![
@@ -289,7 +315,7 @@ pub fn draw(rect: &mut Frame<impl Backend>, app: &mut App) {
                         },
                         None => String::from("last checked"),
                     }),
-                    datetime_utc.format("%d %h %H:%M").to_string(),
+                    last_check.format("%d %h %H:%M").to_string(),
                 ])
                 .style(Style::default().fg(match &app.config.colours {
                     Some(colors) => match &colors.info {
@@ -315,11 +341,36 @@ pub fn draw(rect: &mut Frame<impl Backend>, app: &mut App) {
                     },
                     None => Color::White,
                 })),
+                Row::new(vec![
+                    (match &app.config.icons {
+                        Some(icons) => match &icons.source {
+                            Some(icon) => format!("{} data", icon),
+                            None => String::from("data"),
+                        },
+                        None => String::from("data"),
+                    }),
+                    format!(
+                        "{} {}",
+                        app.provenance(),
+                        relative_age(app.clock.with_timezone(&Utc), app.mirrors.last_check)
+                    ),
+                ])
+                .style(Style::default().fg(match &app.config.colours {
+                    Some(colors) => match &colors.info {
+                        Some(available) if stale => stale_header(available),
+                        Some(available) => source_header(available),
+                        None if stale => Color::Yellow,
+                        None => Color::White,
+                    },
+                    None if stale => Color::Yellow,
+                    None => Color::White,
+                })),
             ];
             let t = Table::new(rows)
                 .header(header)
                 .block(
                     Block::default()
+                        .title(fetch_title(app))
                         .borders(Borders::ALL)
                         .border_style(Style::default()),
                 )
@@ -333,35 +384,104 @@ pub fn draw(rect: &mut Frame<impl Backend>, app: &mut App) {
         }
     }
     {
-        let header_cells = ["Country:", "Mirrors:"].iter().map(|h| {
-            Cell::from(*h).style(
-                Style::default()
-                    .fg(match &app.config.colours {
-                        Some(colors) => match &colors.available_mirrors {
-                            Some(available) => heading_colour(available),
-                            None => Color::White,
-                        },
-                        None => Color::White,
+        let heading = Style::default()
+            .fg(match &app.config.colours {
+                Some(colors) => match &colors.available_mirrors {
+                    Some(available) => heading_colour(available),
+                    None => Color::White,
+                },
+                None => Color::White,
+            })
+            .add_modifier(Modifier::BOLD);
+        let matched = Style::default()
+            .fg(action_key_colour(&app.config.colours))
+            .add_modifier(Modifier::BOLD);
+        let (header_cells, rows, widths) = match app.searching_mirrors() {
+            true => {
+                let header_cells: Vec<_> = ["Mirror:", "Country:", "Protocol:", "Score:"]
+                    .iter()
+                    .map(|h| Cell::from(*h).style(heading))
+                    .collect();
+                let rows: Vec<_> = app
+                    .mirror_matches()
+                    .iter()
+                    .map(|(country, mirror, found)| {
+                        let picked = app.selection(country).is_some_and(|f| f.is_picked(mirror));
+                        let mut host = highlighted(hostname(&mirror.url), &found.indices, matched);
+                        host.0
+                            .insert(0, Span::raw(if picked { "[x] " } else { "[ ] " }));
+                        let country = match country.country.as_str() {
+                            "" => "misc",
+                            name => name,
+                        };
+                        Row::new(vec![
+                            Cell::from(host),
+                            Cell::from(country.to_owned()),
+                            Cell::from(mirror.protocol.to_string()),
+                            Cell::from(match mirror.score {
+                                Some(score) => format!("{:.2}", score),
+                                None => String::from("-"),
+                            }),
+                        ])
                     })
-                    .add_modifier(Modifier::BOLD),
-            )
-        });
-        let header = Row::new(header_cells).height(1);
-        let rows = app.mirrors.countries.iter().filter_map(|resp| {
-            if resp
-                .country
-                .to_lowercase()
-                .contains(&app.country_filter.to_lowercase())
-            {
-                let mut item_name = resp.country.as_str();
-                if item_name.is_empty() {
-                    item_name = "misc"
-                }
-                let row = vec![item_name.to_owned(), resp.mirrors.len().to_string()];
-                return Some(Row::new(row.into_iter()));
+                    .collect();
+                let widths = vec![
+                    Constraint::Percentage(50),
+                    Constraint::Percentage(25),
+                    Constraint::Length(10),
+                    Constraint::Min(7),
+                ];
+                (header_cells, rows, widths)
+            }
+            false => {
+                let (sort_key, sort_direction) = app.config.sort();
+                let header_cells: Vec<_> = [
+                    (SortKey::Country, "Country"),
+                    (SortKey::Mirrors, "Mirrors"),
+                    (SortKey::Score, "Score"),
+                    (SortKey::Delay, "Delay"),
+                    (SortKey::Active, "Active"),
+                    (SortKey::Https, "HTTPS"),
+                ]
+                .iter()
+                .map(|(key, name)| {
+                    let marker = match (*key == sort_key, sort_direction) {
+                        (false, _) => "",
+                        (true, SortDirection::Ascending) => " ▲",
+                        (true, SortDirection::Descending) => " ▼",
+                    };
+                    Cell::from(format!("{}:{}", name, marker)).style(heading)
+                })
+                .collect();
+                let now = Utc::now();
+                let rows: Vec<_> = app
+                    .country_matches()
+                    .iter()
+                    .map(|(resp, found)| {
+                        let mut item_name = resp.country.as_str();
+                        if item_name.is_empty() {
+                            item_name = "misc"
+                        }
+                        let stats = CountryStats::new(resp, &app.protocols);
+                        let mut row =
+                            vec![Cell::from(highlighted(item_name, &found.indices, matched))];
+                        row.extend(country_stats(&stats, now).into_iter().map(Cell::from));
+                        Row::new(row)
+                    })
+                    .collect();
+                let widths = vec![
+                    Constraint::Percentage(30),
+                    Constraint::Length(10),
+                    Constraint::Length(9),
+                    Constraint::Length(9),
+                    Constraint::Length(10),
+                    Constraint::Min(9),
+                ];
+                (header_cells, rows, widths)
             }
-            None
-        });
+        };
+        let header = Row::new(header_cells).height(1);
+        let len = rows.len();
         let (fg, bg, reversed): (String, String, bool) = match &app.config.colours {
             Some(cols) => match &cols.available_mirrors {
                 Some(cols) => match &cols.highlight_fg {
@@ -457,12 +577,15 @@ pub fn draw(rect: &mut Frame<impl Backend>, app: &mut App) {
             )
             .highlight_style(selected_style)
             .highlight_symbol(&symbol)
-            .widths(&[
-                Constraint::Percentage(50),
-                Constraint::Length(30),
-                Constraint::Min(10),
-            ]);
+            .widths(&widths);
         rect.render_stateful_widget(t, chunks[0], &mut app.table);
+        app.areas.widget(Widgets::Mirrors, chunks[0]);
+        app.areas.table(ScrollableTables::AllMirrors).drawn(
+            chunks[0],
+            true,
+            app.table.selected(),
+            len,
+        );
 
         {
             let chunks = Layout::default()
@@ -474,8 +597,13 @@ pub fn draw(rect: &mut Frame<impl Backend>, app: &mut App) {
                 if item_name.is_empty() {
                     item_name = "misc"
                 }
-                let row = vec![item_name.to_owned()];
-                Row::new(row.into_iter())
+                let row = vec![format!(
+                    "{} ({}/{})",
+                    item_name,
+                    resp.mirrors.len(),
+                    resp.country.mirrors.len()
+                )];
+                Row::new(row)
             });
 
             let (fg, bg, reversed): (String, String, bool) = match &app.config.colours {
@@ -592,14 +720,37 @@ pub fn draw(rect: &mut Frame<impl Backend>, app: &mut App) {
                     Constraint::Min(10),
                 ]);
             rect.render_stateful_widget(t, chunks[0], &mut app.selected_table);
-            let rows = app.focused_country().mirrors.iter().map(|resp| {
-                let mut item_name = resp.url.as_str();
-                if item_name.is_empty() {
-                    item_name = "misc"
-                }
-                let row = vec![item_name.to_owned()];
-                Row::new(row.into_iter())
-            });
+            app.areas.widget(Widgets::SelectedCountries, chunks[0]);
+            app.areas.table(ScrollableTables::SavedMirrors).drawn(
+                chunks[0],
+                false,
+                app.selected_table.selected(),
+                app.selected_countries.len(),
+            );
+            let now = app.clock.with_timezone(&Utc);
+            let selection = app.selection(app.focused_country());
+            let rows: Vec<_> = app
+                .focused_mirrors()
+                .into_iter()
+                .map(|resp| {
+                    let mut item_name = resp.url.as_str();
+                    if item_name.is_empty() {
+                        item_name = "misc"
+                    }
+                    // picked mirrors show where they are exported among the country's
+                    let checkbox = match selection.and_then(|f| f.rank(resp)) {
+                        Some(rank) => format!("[{}]", rank + 1),
+                        None => String::from("[ ]"),
+                    };
+                    let synced = match resp.last_sync {
+                        Some(last_sync) => format!("synced {}", relative_age(now, last_sync)),
+                        None => String::from("never synced"),
+                    };
+                    let row = vec![format!("{} {}", checkbox, item_name), synced];
+                    Row::new(row)
+                })
+                .collect();
+            let len = rows.len();
 
             let header_cells = ["per country:"].iter().map(|h| {
                 Cell::from(*h).style(
@@ -619,16 +770,32 @@ pub fn draw(rect: &mut Frame<impl Backend>, app: &mut App) {
                 .header(header)
                 .block(
                     Block::default()
-                        .title("mirrors")
+                        .title(Spans::from(vec![
+                            Span::styled(
+                                "mirror".to_string(),
+                                Style::default().add_modifier(Modifier::BOLD),
+                            ),
+                            Span::styled(
+                                "s".to_string(),
+                                Style::default()
+                                    .fg(action_key_colour(&app.config.colours))
+                                    .add_modifier(Modifier::BOLD),
+                            ),
+                        ]))
                         .borders(Borders::ALL)
                         .border_style(Style::default()),
                 )
-                .widths(&[
-                    Constraint::Percentage(100),
-                    Constraint::Length(30),
-                    Constraint::Min(10),
-                ]);
-            rect.render_widget(t, chunks[1]);
+                .highlight_symbol(&symbol)
+                .highlight_style(selected_style)
+                .widths(&[Constraint::Percentage(70), Constraint::Percentage(30)]);
+            rect.render_stateful_widget(t, chunks[1], &mut app.mirrors_table);
+            app.areas.widget(Widgets::CountryMirrors, chunks[1]);
+            app.areas.table(ScrollableTables::FocusedCountry).drawn(
+                chunks[1],
+                true,
+                app.mirrors_table.selected(),
+                len,
+            );
         }
     }
     let gauge = Gauge::default()
@@ -653,12 +820,221 @@ pub fn draw(rect: &mut Frame<impl Backend>, app: &mut App) {
         .percent(70);
     rect.render_widget(gauge, chunks[2]);
     rect.render_widget(block_0, chunks[0]);
+    if app.state.focused_widget() == Some(&Widgets::MirrorDetails) {
+        let area = centered(chunks[1], 70, 90);
+        draw_mirror_details(rect, app, area);
+        app.areas.widget(Widgets::MirrorDetails, area);
+    }
+}
+
+/// The metrics of the highlighted mirror, drawn in `area`
+fn draw_mirror_details(rect: &mut Frame<impl Backend>, app: &App, area: Rect) {
+    let now = app.clock.with_timezone(&Utc);
+    let (title, rows) = match app.highlighted_mirror() {
+        Some(mirror) => {
+            let rows = mirror_metrics(mirror, now, app.mirrors.cutoff)
+                .into_iter()
+                .map(|(name, value, rating)| {
+                    Row::new(vec![
+                        Cell::from(name).style(Style::default().add_modifier(Modifier::BOLD)),
+                        Cell::from(value).style(Style::default().fg(rating_colour(rating))),
+                    ])
+                })
+                .collect();
+            (mirror.url.clone(), rows)
+        }
+        None => (String::from("no mirror highlighted"), vec![]),
+    };
+    let t = Table::new(rows)
+        .block(
+            Block::default()
+                .title(Span::styled(
+                    title,
+                    Style::default().add_modifier(Modifier::BOLD),
+                ))
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(action_key_colour(&app.config.colours))),
+        )
+        .widths(&[Constraint::Length(18), Constraint::Min(10)]);
+    rect.render_widget(Clear, area);
+    rect.render_widget(t, area);
+}
+
+/// `percent_x` by `percent_y` of `area`, in its middle
+fn centered(area: Rect, percent_x: u16, percent_y: u16) -> Rect {
+    let margin_x = area.width * (100 - percent_x) / 200;
+    let margin_y = area.height * (100 - percent_y) / 200;
+    Rect::new(
+        area.x + margin_x,
+        area.y + margin_y,
+        area.width - 2 * margin_x,
+        area.height - 2 * margin_y,
+    )
+}
+
+/// How a metric of a mirror compares to what is expected of a good one
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Rating {
+    Good,
+    Fair,
+    Poor,
+    /// Not reported, or nothing to judge
+    Neutral,
+}
+
+fn rating_colour(rating: Rating) -> Color {
+    match rating {
+        Rating::Good => Color::Green,
+        Rating::Fair => Color::Yellow,
+        Rating::Poor => Color::Red,
+        Rating::Neutral => Color::Gray,
+    }
+}
+
+/// Every metric archlinux.org reports for `mirror`, rated. Syncs are judged against `cutoff`,
+/// after which archlinux.org considers a mirror out of date
+fn mirror_metrics(
+    mirror: &Mirror,
+    now: DateTime<Utc>,
+    cutoff: Duration,
+) -> Vec<(&'static str, String, Rating)> {
+    fn rate<T: PartialOrd>(value: T, good: T, fair: T) -> Rating {
+        if value <= good {
+            Rating::Good
+        } else if value <= fair {
+            Rating::Fair
+        } else {
+            Rating::Poor
+        }
+    }
+    fn flag(value: bool, missing: Rating) -> (String, Rating) {
+        match value {
+            true => (String::from("yes"), Rating::Good),
+            false => (String::from("no"), missing),
+        }
+    }
+    let unknown = || (String::from("unknown"), Rating::Neutral);
+    let since = |age: Duration| {
+        relative_age(now, now - age)
+            .trim_end_matches(" ago")
+            .to_owned()
+    };
+
+    let score = match mirror.score {
+        Some(score) => (format!("{:.2}", score), rate(score, 2.0, 5.0)),
+        None => unknown(),
+    };
+    let completion = match mirror.completion_pct {
+        Some(pct) => {
+            let rating = if pct >= 1.0 {
+                Rating::Good
+            } else if pct >= 0.9 {
+                Rating::Fair
+            } else {
+                Rating::Poor
+            };
+            (format!("{:.1}%", pct * 100.0), rating)
+        }
+        None => unknown(),
+    };
+    let delay = match mirror.delay {
+        Some(delay) => (since(delay), rate(delay, Duration::hours(1), cutoff)),
+        None => unknown(),
+    };
+    let last_sync = match mirror.last_sync {
+        Some(last_sync) => (
+            relative_age(now, last_sync),
+            rate(now - last_sync, Duration::hours(1), cutoff),
+        ),
+        None => (String::from("never"), Rating::Poor),
+    };
+    let duration_avg = match mirror.duration_avg {
+        Some(avg) => (format!("{:.2}s", avg), rate(avg, 1.0, 3.0)),
+        None => unknown(),
+    };
+    let duration_stddev = match mirror.duration_stddev {
+        Some(stddev) => (format!("{:.2}s", stddev), rate(stddev, 0.5, 1.5)),
+        None => unknown(),
+    };
+    let rows = [
+        ("protocol", (mirror.protocol.to_string(), Rating::Neutral)),
+        ("active", flag(mirror.active, Rating::Poor)),
+        ("score", score),
+        ("completion", completion),
+        ("delay", delay),
+        ("last sync", last_sync),
+        ("duration avg", duration_avg),
+        ("duration stddev", duration_stddev),
+        ("isos", flag(mirror.isos, Rating::Fair)),
+        ("ipv4", flag(mirror.ipv4, Rating::Fair)),
+        ("ipv6", flag(mirror.ipv6, Rating::Fair)),
+        ("details", (mirror.details.clone(), Rating::Neutral)),
+    ];
+    rows.into_iter()
+        .map(|(name, (value, rating))| (name, value, rating))
+        .collect()
+}
+
+/// `text` with the characters at `indices` in `style`
+fn highlighted(text: &str, indices: &[usize], style: Style) -> Spans<'static> {
+    let mut spans: Vec<(bool, String)> = vec![];
+    for (index, ch) in text.chars().enumerate() {
+        let matched = indices.contains(&index);
+        match spans.last_mut() {
+            Some((last, span)) if *last == matched => span.push(ch),
+            _ => spans.push((matched, ch.to_string())),
+        }
+    }
+    Spans::from(
+        spans
+            .into_iter()
+            .map(|(matched, span)| match matched {
+                true => Span::styled(span, style),
+                false => Span::raw(span),
+            })
+            .collect::<Vec<_>>(),
+    )
+}
+
+/// The columns of the available mirrors table after the country name
+fn country_stats(stats: &CountryStats, now: DateTime<Utc>) -> [String; 5] {
+    [
+        stats.mirrors.to_string(),
+        match stats.score {
+            Some(score) => format!("{:.2}", score),
+            None => String::from("-"),
+        },
+        match stats.delay {
+            Some(delay) => relative_age(now, now - delay)
+                .trim_end_matches(" ago")
+                .to_owned(),
+            None => String::from("-"),
+        },
+        stats.active.to_string(),
+        format!("{:.0}%", stats.https * 100.0),
+    ]
+}
+
+/// How long before `now` something happened, in its largest unit, e.g. `3h ago`
+fn relative_age(now: DateTime<Utc>, then: DateTime<Utc>) -> String {
+    let age = now - then;
+    if age < Duration::zero() {
+        String::from("just now")
+    } else if age < Duration::minutes(1) {
+        format!("{}s ago", age.num_seconds())
+    } else if age < Duration::hours(1) {
+        format!("{}m ago", age.num_minutes())
+    } else if age < Duration::days(1) {
+        format!("{}h ago", age.num_hours())
+    } else {
+        format!("{}d ago", age.num_days())
+    }
 }
 
 fn get_os_name() -> String {
     let os = std::fs::read_to_string("/etc/os-release").unwrap();
     let os: Vec<_> = os.lines().collect();
-    let os = os.get(0).unwrap();
+    let os = os.first().unwrap();
     let os: Vec<_> = os.split('\"').collect();
     os.get(1).unwrap().to_string()
 }
@@ -695,7 +1071,7 @@ fn action_key_colour(colours: &Option<Colours>) -> tui::style::Color {
     }
 }
 fn rgb_from_hex(val: String) -> Option<(u8, u8, u8)> {
-    if val.chars().into_iter().count() == 6 {
+    if val.chars().count() == 6 {
         match u8::from_str_radix(&val[0..2], 16) {
             Ok(red) => match u8::from_str_radix(&val[2..4], 16) {
                 Ok(green) => match u8::from_str_radix(&val[4..6], 16) {
@@ -833,6 +1209,57 @@ fn now_header(colours: &Info) -> tui::style::Color {
     }
 }
 
+/// Why the status was not fetched, in the stale colour, followed by notes on it that are not
+/// errors
+fn fetch_title(app: &App) -> Spans<'static> {
+    let mut title = vec![];
+    if let Some(error) = app.fetch_progress().or_else(|| app.fetch_error()) {
+        title.push(Span::styled(
+            error.to_owned(),
+            Style::default().fg(match &app.config.colours {
+                Some(colors) => match &colors.info {
+                    Some(available) => stale_header(available),
+                    None => Color::Yellow,
+                },
+                None => Color::Yellow,
+            }),
+        ));
+    }
+    if let Some(note) = app.fetch_note() {
+        if !title.is_empty() {
+            title.push(Span::raw(", "));
+        }
+        title.push(Span::raw(note.to_owned()));
+    }
+    Spans::from(title)
+}
+
+fn source_header(colours: &Info) -> tui::style::Color {
+    match &colours.source {
+        Some(col) => {
+            if let Some((red, green, blue)) = rgb_from_hex(col.to_string()) {
+                Color::Rgb(red, green, blue)
+            } else {
+                Color::Blue
+            }
+        }
+        None => Color::Blue,
+    }
+}
+
+fn stale_header(colours: &Info) -> tui::style::Color {
+    match &colours.stale {
+        Some(col) => {
+            if let Some((red, green, blue)) = rgb_from_hex(col.to_string()) {
+                Color::Rgb(red, green, blue)
+            } else {
+                Color::Yellow
+            }
+        }
+        None => Color::Yellow,
+    }
+}
+
 fn app_name(colours: &Info) -> tui::style::Color {
     match &colours.app {
         Some(col) => {
@@ -845,3 +1272,82 @@ fn app_name(colours: &Info) -> tui::style::Color {
         None => Color::Blue,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, TimeZone, Utc};
+
+    use linux_mirrors::archlinux::internal::Mirror;
+
+    use tui::{
+        style::{Modifier, Style},
+        text::Span,
+    };
+
+    use super::{highlighted, mirror_metrics, relative_age, Rating};
+
+    #[test]
+    fn should_highlight_matched_characters() {
+        let style = Style::default().add_modifier(Modifier::BOLD);
+        let spans = highlighted("Türkiye", &[1, 2, 6], style);
+        assert_eq!(
+            spans.0,
+            [
+                Span::raw("T"),
+                Span::styled("ür", style),
+                Span::raw("kiy"),
+                Span::styled("e", style),
+            ]
+        );
+        assert_eq!(highlighted("Chile", &[], style).0, [Span::raw("Chile")]);
+    }
+
+    #[test]
+    fn should_show_age_in_largest_unit() {
+        let now = Utc.ymd(2022, 2, 13).and_hms(12, 8, 4);
+        assert_eq!(relative_age(now, now - Duration::seconds(42)), "42s ago");
+        assert_eq!(relative_age(now, now - Duration::minutes(39)), "39m ago");
+        assert_eq!(relative_age(now, now - Duration::minutes(200)), "3h ago");
+        assert_eq!(relative_age(now, now - Duration::days(9)), "9d ago");
+        assert_eq!(relative_age(now, now + Duration::seconds(5)), "just now");
+    }
+
+    #[test]
+    fn should_rate_mirror_metrics() {
+        let now = Utc.ymd(2022, 2, 13).and_hms(12, 8, 4);
+        let rated = |mirror: &Mirror| {
+            mirror_metrics(mirror, now, Duration::hours(24))
+                .into_iter()
+                .map(|(name, value, rating)| (name, (value, rating)))
+                .collect::<std::collections::HashMap<_, _>>()
+        };
+        let mut good = Mirror::default();
+        good.score = Some(1.2);
+        good.completion_pct = Some(1.0);
+        good.delay = Some(Duration::minutes(20));
+        good.last_sync = Some(now - Duration::minutes(30));
+        good.duration_avg = Some(0.4);
+        good.active = true;
+        let good = rated(&good);
+        assert_eq!(good["score"], ("1.20".to_owned(), Rating::Good));
+        assert_eq!(good["completion"], ("100.0%".to_owned(), Rating::Good));
+        assert_eq!(good["delay"], ("20m".to_owned(), Rating::Good));
+        assert_eq!(good["last sync"], ("30m ago".to_owned(), Rating::Good));
+        assert_eq!(good["duration avg"].1, Rating::Good);
+        assert_eq!(good["duration stddev"].1, Rating::Neutral);
+        assert_eq!(good["active"].1, Rating::Good);
+
+        let mut poor = Mirror::default();
+        poor.score = Some(3.0);
+        poor.completion_pct = Some(0.5);
+        poor.delay = Some(Duration::hours(30));
+        poor.last_sync = None;
+        let poor = rated(&poor);
+        assert_eq!(poor["score"].1, Rating::Fair);
+        assert_eq!(poor["completion"].1, Rating::Poor);
+        assert_eq!(poor["delay"], ("1d".to_owned(), Rating::Poor));
+        assert_eq!(poor["last sync"], ("never".to_owned(), Rating::Poor));
+        assert_eq!(poor["active"].1, Rating::Poor);
+        assert_eq!(poor["ipv6"].1, Rating::Fair);
+    }
+}
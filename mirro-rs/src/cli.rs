@@ -0,0 +1,584 @@
+use std::{collections::HashMap, fmt::Display, io, path::PathBuf, str::FromStr};
+
+use chrono::{DateTime, Duration, Utc};
+use clap::{App, Arg, ArgMatches};
+use linux_mirrors::{
+    archlinux::{
+        internal::{ArchMirrors, Mirror, Protocol},
+        rate::{rate, RateOptions},
+    },
+    Client,
+};
+use tracing::error;
+
+use crate::{
+    app::{
+        config::MirrorsConfig,
+        export::{save, write_mirrorlist, Header, Server},
+    },
+    arch_linux, describe_retry, explain, skipped,
+};
+
+pub const SUBCOMMAND: &str = "cli";
+
+/// Exit status of the headless mode
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Exit {
+    Success = 0,
+    /// The mirror status could not be downloaded and none was cached
+    FetchFailed = 1,
+    InvalidArguments = 2,
+    /// Nothing was left after filtering
+    NoMirrors = 3,
+    /// The mirrorlist could not be written
+    WriteFailed = 4,
+}
+
+/// How the selected mirrors are ordered
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortKey {
+    /// Most recently synchronised first
+    Age,
+    /// Fastest download first
+    Rate,
+    Country,
+    /// Lowest (best) mirror score first
+    Score,
+    /// Lowest sync delay first
+    Delay,
+}
+
+impl SortKey {
+    const VALUES: [&'static str; 5] = ["age", "rate", "country", "score", "delay"];
+}
+
+impl FromStr for SortKey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "age" => Ok(Self::Age),
+            "rate" => Ok(Self::Rate),
+            "country" => Ok(Self::Country),
+            "score" => Ok(Self::Score),
+            "delay" => Ok(Self::Delay),
+            _ => Err(format!("unknown sort key: {}", s)),
+        }
+    }
+}
+
+impl Display for SortKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let str = match self {
+            SortKey::Age => "age",
+            SortKey::Rate => "rate",
+            SortKey::Country => "country",
+            SortKey::Score => "score",
+            SortKey::Delay => "delay",
+        };
+        write!(f, "{}", str)
+    }
+}
+
+/// The protocols pacman can download from, used when `--protocol` is not given
+pub const DEFAULT_PROTOCOLS: [Protocol; 2] = [Protocol::Https, Protocol::Http];
+
+/// Filters and ordering requested on the command line
+#[derive(Debug, Clone, PartialEq)]
+pub struct Options {
+    /// Country names or codes, empty for all countries
+    pub countries: Vec<String>,
+    /// Empty for all protocols, [`DEFAULT_PROTOCOLS`] when `--protocol` is not given
+    pub protocols: Vec<Protocol>,
+    /// Only mirrors synchronised within this many hours
+    pub age: Option<f64>,
+    /// Only the n most recently synchronised mirrors
+    pub latest: Option<usize>,
+    /// Only the n fastest mirrors
+    pub fastest: Option<usize>,
+    pub sort: Option<SortKey>,
+    /// At most n mirrors in the mirrorlist
+    pub number: Option<usize>,
+    /// Minimum completion percentage, 0 to 100
+    pub completion_percent: f64,
+    /// Write the mirrorlist here instead of stdout
+    pub save: Option<PathBuf>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            countries: vec![],
+            protocols: DEFAULT_PROTOCOLS.to_vec(),
+            age: None,
+            latest: None,
+            fastest: None,
+            sort: None,
+            number: None,
+            completion_percent: 100.0,
+            save: None,
+        }
+    }
+}
+
+/// A mirror that passed the filters, along with the country it is listed under
+#[derive(Debug, Clone, PartialEq)]
+pub struct Candidate {
+    pub country: String,
+    pub mirror: Mirror,
+}
+
+pub fn subcommand() -> App<'static> {
+    App::new(SUBCOMMAND)
+        .about("Generate a mirrorlist without the TUI, accepts reflector's flags")
+        .arg(
+            Arg::new("country")
+                .long("country")
+                .short('c')
+                .takes_value(true)
+                .multiple_occurrences(true)
+                .use_delimiter(true)
+                .help("Only mirrors in these countries, by name or code"),
+        )
+        .arg(
+            Arg::new("protocol")
+                .long("protocol")
+                .short('p')
+                .takes_value(true)
+                .multiple_occurrences(true)
+                .use_delimiter(true)
+                .help("Only mirrors served over these protocols [default: https,http]"),
+        )
+        .arg(
+            Arg::new("age")
+                .long("age")
+                .short('a')
+                .takes_value(true)
+                .help("Only mirrors synchronised in the last n hours"),
+        )
+        .arg(
+            Arg::new("latest")
+                .long("latest")
+                .short('l')
+                .takes_value(true)
+                .help("Only the n most recently synchronised mirrors"),
+        )
+        .arg(
+            Arg::new("fastest")
+                .long("fastest")
+                .short('f')
+                .takes_value(true)
+                .help("Only the n fastest mirrors, rates every mirror left after filtering"),
+        )
+        .arg(
+            Arg::new("sort")
+                .long("sort")
+                .takes_value(true)
+                .possible_values(SortKey::VALUES)
+                .help("Sort the mirrorlist"),
+        )
+        .arg(
+            Arg::new("number")
+                .long("number")
+                .short('n')
+                .takes_value(true)
+                .help("Write at most n mirrors"),
+        )
+        .arg(
+            Arg::new("completion-percent")
+                .long("completion-percent")
+                .takes_value(true)
+                .default_value("100")
+                .help("Only mirrors that are at least this percent complete"),
+        )
+        .arg(
+            Arg::new("save")
+                .long("save")
+                .takes_value(true)
+                .help("Save the mirrorlist to this file instead of printing it"),
+        )
+}
+
+impl Options {
+    pub fn from_matches(m: &ArgMatches) -> Result<Self, String> {
+        fn list(m: &ArgMatches, name: &str) -> Vec<String> {
+            m.values_of(name)
+                .map(|f| f.map(|f| f.trim().to_owned()).collect())
+                .unwrap_or_default()
+        }
+        fn parse<T: FromStr>(m: &ArgMatches, name: &str) -> Result<Option<T>, String>
+        where
+            T::Err: Display,
+        {
+            m.value_of(name)
+                .map(|f| f.parse().map_err(|e| format!("--{}: {}", name, e)))
+                .transpose()
+        }
+
+        let completion_percent = parse(m, "completion-percent")?.unwrap_or(100.0);
+        if !(0.0..=100.0).contains(&completion_percent) {
+            return Err("--completion-percent: must be between 0 and 100".to_owned());
+        }
+        Ok(Self {
+            countries: list(m, "country"),
+            protocols: match list(m, "protocol") {
+                protocols if protocols.is_empty() => DEFAULT_PROTOCOLS.to_vec(),
+                protocols => protocols
+                    .iter()
+                    .map(|f| Protocol::from(f.as_str()))
+                    .collect(),
+            },
+            age: parse(m, "age")?,
+            latest: parse(m, "latest")?,
+            fastest: parse(m, "fastest")?,
+            sort: parse(m, "sort")?,
+            number: parse(m, "number")?,
+            completion_percent,
+            save: m.value_of("save").map(PathBuf::from),
+        })
+    }
+
+    /// The options that were set, for the mirrorlist header
+    fn describe(&self) -> Vec<(String, String)> {
+        let mut filters = vec![];
+        if !self.protocols.is_empty() {
+            let protocols: Vec<_> = self.protocols.iter().map(|f| f.to_string()).collect();
+            filters.push(("Protocols".to_owned(), protocols.join(", ")));
+        }
+        if let Some(age) = self.age {
+            filters.push(("Age".to_owned(), format!("{} hours", age)));
+        }
+        filters.push((
+            "Completion".to_owned(),
+            format!("{}%", self.completion_percent),
+        ));
+        if let Some(latest) = self.latest {
+            filters.push(("Latest".to_owned(), latest.to_string()));
+        }
+        if let Some(fastest) = self.fastest {
+            filters.push(("Fastest".to_owned(), fastest.to_string()));
+        }
+        if let Some(sort) = self.sort {
+            filters.push(("Sorted by".to_owned(), sort.to_string()));
+        }
+        if let Some(number) = self.number {
+            filters.push(("Number".to_owned(), number.to_string()));
+        }
+        filters
+    }
+}
+
+/// Applies the country, protocol, age, completion and `--latest` options to the active mirrors
+pub fn filter(mirrors: &ArchMirrors, options: &Options, now: DateTime<Utc>) -> Vec<Candidate> {
+    let mut query = options
+        .countries
+        .iter()
+        .fold(mirrors.query(), |query, country| query.country(country))
+        .active(true)
+        .protocols(options.protocols.iter().cloned())
+        .min_completion(options.completion_percent)
+        .now(now);
+    if let Some(age) = options.age {
+        query = query.max_age(Duration::seconds((age * 3600.0) as i64));
+    }
+    let mut candidates: Vec<_> = query
+        .mirrors()
+        .into_iter()
+        .map(|(country, mirror)| Candidate {
+            country: country.country.clone(),
+            mirror: mirror.clone(),
+        })
+        .collect();
+    if let Some(latest) = options.latest {
+        sort(&mut candidates, SortKey::Age);
+        candidates.truncate(latest);
+    }
+    candidates
+}
+
+/// Sorts by every key but [`SortKey::Rate`], which needs [`by_speed`]
+pub fn sort(candidates: &mut [Candidate], key: SortKey) {
+    match key {
+        SortKey::Age => candidates.sort_by_key(|f| std::cmp::Reverse(f.mirror.last_sync)),
+        SortKey::Country => candidates.sort_by(|a, b| a.country.cmp(&b.country)),
+        SortKey::Score => candidates.sort_by(|a, b| {
+            let a = a.mirror.score.unwrap_or(f64::MAX);
+            let b = b.mirror.score.unwrap_or(f64::MAX);
+            a.total_cmp(&b)
+        }),
+        SortKey::Delay => {
+            candidates.sort_by_key(|f| f.mirror.delay.unwrap_or_else(Duration::max_value))
+        }
+        SortKey::Rate => {}
+    }
+}
+
+/// Rates every candidate with `client` and returns those that could be downloaded from, fastest
+/// first
+pub async fn by_speed(client: &Client, candidates: Vec<Candidate>) -> Vec<Candidate> {
+    let mirrors = candidates.iter().map(|f| f.mirror.clone()).collect();
+    let mut by_url: HashMap<_, _> = candidates
+        .into_iter()
+        .map(|f| (f.mirror.url.clone(), f))
+        .collect();
+    rate(client, mirrors, &RateOptions::default())
+        .await
+        .into_iter()
+        .filter_map(|f| by_url.remove(&f.mirror.url))
+        .collect()
+}
+
+/// Runs the headless mode, returning the status the process should exit with
+pub async fn run(m: &ArgMatches, config: &MirrorsConfig) -> Exit {
+    let options = match Options::from_matches(m) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("{}", e);
+            return Exit::InvalidArguments;
+        }
+    };
+    let client = match config.client() {
+        Ok(client) => client,
+        Err(e) => {
+            eprintln!("{}", explain(&e));
+            return Exit::InvalidArguments;
+        }
+    };
+    let provider = arch_linux(client.clone()).with_retry(config.retry_policy());
+    let fetched = provider
+        .fetch_reporting(|retry| eprintln!("{}", describe_retry(retry)))
+        .await;
+    let mirrors = match fetched {
+        Ok(mirrors) => {
+            if let Some(skipped) = skipped(&mirrors) {
+                eprintln!("{}", skipped);
+            }
+            mirrors
+        }
+        Err(e) => {
+            error!("{e}");
+            eprintln!("could not fetch the mirror status: {}", explain(&e));
+            match provider.cached() {
+                Ok(Some((mirrors, fetched))) => {
+                    eprintln!("using the status cached at {}", fetched);
+                    mirrors
+                }
+                _ => return Exit::FetchFailed,
+            }
+        }
+    };
+
+    let mut candidates = filter(&mirrors, &options, Utc::now());
+    let mut rated = false;
+    if let Some(fastest) = options.fastest {
+        candidates = by_speed(&client, candidates).await;
+        candidates.truncate(fastest);
+        rated = true;
+    }
+    match options.sort {
+        Some(SortKey::Rate) if !rated => candidates = by_speed(&client, candidates).await,
+        Some(SortKey::Rate) => {}
+        Some(key) => sort(&mut candidates, key),
+        None => {}
+    }
+    if let Some(number) = options.number {
+        candidates.truncate(number);
+    }
+    if candidates.is_empty() {
+        eprintln!("no mirrors matched the given filters");
+        return Exit::NoMirrors;
+    }
+
+    let mut countries: Vec<String> = vec![];
+    for f in candidates.iter() {
+        if !countries.contains(&f.country) {
+            countries.push(f.country.clone());
+        }
+    }
+    let header = Header {
+        when: Utc::now(),
+        filters: options.describe(),
+        countries,
+    };
+    let servers: Vec<_> = candidates
+        .into_iter()
+        .map(|f| Server {
+            country: f.country,
+            url: f.mirror.url,
+        })
+        .collect();
+
+    let written = match &options.save {
+        Some(path) => save(path, config.export_backups(), |fs| {
+            write_mirrorlist(fs, &header, &servers)
+        }),
+        None => write_mirrorlist(&mut io::stdout().lock(), &header, &servers),
+    };
+    match written {
+        Ok(_) => Exit::Success,
+        Err(e) => {
+            error!("{e}");
+            eprintln!("could not write the mirrorlist: {}", e);
+            Exit::WriteFailed
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    fn mirrors() -> ArchMirrors {
+        serde_json::from_str(include_str!("../../assets/arch_mirrors.json")).unwrap()
+    }
+
+    fn now() -> DateTime<Utc> {
+        Utc.ymd(2022, 2, 13).and_hms(12, 8, 4)
+    }
+
+    fn options(args: &[&str]) -> Options {
+        let m = subcommand()
+            .try_get_matches_from(std::iter::once(SUBCOMMAND).chain(args.iter().copied()))
+            .unwrap();
+        Options::from_matches(&m).unwrap()
+    }
+
+    #[test]
+    fn should_parse_reflector_flags() {
+        let options = options(&[
+            "--country",
+            "Germany,fr",
+            "--protocol",
+            "HTTPS",
+            "--age",
+            "12",
+            "--latest",
+            "20",
+            "--sort",
+            "score",
+            "--number",
+            "5",
+            "--completion-percent",
+            "90",
+            "--save",
+            "/tmp/mirrorlist",
+        ]);
+        assert_eq!(
+            options,
+            Options {
+                countries: vec!["Germany".to_owned(), "fr".to_owned()],
+                protocols: vec![Protocol::Https],
+                age: Some(12.0),
+                latest: Some(20),
+                fastest: None,
+                sort: Some(SortKey::Score),
+                number: Some(5),
+                completion_percent: 90.0,
+                save: Some(PathBuf::from("/tmp/mirrorlist")),
+            }
+        );
+    }
+
+    #[test]
+    fn should_reject_invalid_values() {
+        let m = subcommand()
+            .try_get_matches_from([SUBCOMMAND, "--number", "many"])
+            .unwrap();
+        assert!(Options::from_matches(&m).is_err());
+        let m = subcommand()
+            .try_get_matches_from([SUBCOMMAND, "--completion-percent", "120"])
+            .unwrap();
+        assert!(Options::from_matches(&m).is_err());
+    }
+
+    #[test]
+    fn should_default_to_protocols_pacman_can_download_from() {
+        assert_eq!(options(&[]), Options::default());
+        let candidates = filter(&mirrors(), &options(&[]), now());
+        assert!(!candidates.is_empty());
+        assert!(candidates
+            .iter()
+            .all(|f| DEFAULT_PROTOCOLS.contains(&f.mirror.protocol)));
+        assert!(
+            filter(&mirrors(), &options(&["--protocol", "rsync"]), now())
+                .iter()
+                .all(|f| f.mirror.protocol == Protocol::Rsync)
+        );
+    }
+
+    #[test]
+    fn should_filter_by_country_name_or_code_and_protocol() {
+        let candidates = filter(
+            &mirrors(),
+            &options(&["--country", "germany,FR", "--protocol", "https"]),
+            now(),
+        );
+        assert!(!candidates.is_empty());
+        assert!(candidates
+            .iter()
+            .all(|f| (f.country == "Germany" || f.country == "France")
+                && f.mirror.protocol == Protocol::Https));
+        assert!(candidates.iter().any(|f| f.country == "France"));
+    }
+
+    #[test]
+    fn should_filter_by_age_and_completion() {
+        let candidates = filter(
+            &mirrors(),
+            &options(&["--age", "1", "--completion-percent", "100"]),
+            now(),
+        );
+        assert!(!candidates.is_empty());
+        assert!(candidates.iter().all(|f| {
+            f.mirror.completion_pct == Some(1.0)
+                && now() - f.mirror.last_sync.unwrap() <= Duration::hours(1)
+        }));
+    }
+
+    #[test]
+    fn should_leave_out_inactive_mirrors() {
+        let mut mirrors = mirrors();
+        let inactive = &mut mirrors
+            .countries
+            .iter_mut()
+            .find(|f| f.country == "Germany")
+            .unwrap()
+            .mirrors[0];
+        inactive.active = false;
+        let url = inactive.url.clone();
+
+        let options = options(&[
+            "--country",
+            "DE",
+            "--completion-percent",
+            "0",
+            "--protocol",
+            "https,http,rsync",
+        ]);
+        let candidates = filter(&mirrors, &options, now());
+        assert!(!candidates.is_empty());
+        assert!(candidates
+            .iter()
+            .all(|f| f.mirror.active && f.mirror.url != url));
+    }
+
+    #[test]
+    fn should_keep_latest_and_sort() {
+        let mut candidates = filter(&mirrors(), &options(&["--latest", "10"]), now());
+        assert_eq!(candidates.len(), 10);
+        assert!(candidates
+            .windows(2)
+            .all(|f| f[0].mirror.last_sync >= f[1].mirror.last_sync));
+
+        sort(&mut candidates, SortKey::Delay);
+        assert!(candidates.windows(2).all(|f| f[0]
+            .mirror
+            .delay
+            .unwrap_or_else(Duration::max_value)
+            <= f[1].mirror.delay.unwrap_or_else(Duration::max_value)));
+        sort(&mut candidates, SortKey::Country);
+        assert!(candidates.windows(2).all(|f| f[0].country <= f[1].country));
+    }
+}
@@ -9,7 +9,7 @@ use std::{
 use tokio::sync::mpsc::{Receiver, Sender};
 use tracing::error;
 
-use super::{key::Key, InputEvent};
+use super::{key::Key, mouse::Mouse, InputEvent};
 
 pub struct Events {
     rx: Receiver<InputEvent>,
@@ -31,9 +31,19 @@ impl Events {
             loop {
                 // poll for tick rate duration, if no event, sent tick event.
                 if crossterm::event::poll(tick_rate).unwrap() {
-                    if let crossterm::event::Event::Key(key) = crossterm::event::read().unwrap() {
-                        let key = Key::from(key);
-                        if let Err(err) = event_tx.send(InputEvent::Input(key)).await {
+                    let event = match crossterm::event::read().unwrap() {
+                        crossterm::event::Event::Key(key) => {
+                            Some(InputEvent::Input(Key::from(key)))
+                        }
+                        // moves are reported too, only clicks and the wheel are forwarded
+                        crossterm::event::Event::Mouse(mouse) => match Mouse::from(mouse) {
+                            Mouse::Unknown => None,
+                            mouse => Some(InputEvent::Mouse(mouse)),
+                        },
+                        _ => None,
+                    };
+                    if let Some(event) = event {
+                        if let Err(err) = event_tx.send(event).await {
                             error!("{err}");
                         }
                     }
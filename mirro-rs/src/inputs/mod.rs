@@ -1,11 +1,14 @@
-use self::key::Key;
+use self::{key::Key, mouse::Mouse};
 
 pub mod events;
 pub mod key;
+pub mod mouse;
 
 pub enum InputEvent {
     /// An input event occurred.
     Input(Key),
+    /// A mouse button was pressed or the wheel scrolled.
+    Mouse(Mouse),
     /// An tick event occurred.
     Tick,
 }
@@ -0,0 +1,38 @@
+use crossterm::event::{self, MouseButton, MouseEventKind};
+
+/// Represents a mouse event at a cell of the terminal.
+#[derive(PartialEq, Eq, Clone, Copy, Hash, Debug)]
+pub enum Mouse {
+    /// Left button pressed
+    Click { column: u16, row: u16 },
+    /// Wheel scrolled up
+    ScrollUp { column: u16, row: u16 },
+    /// Wheel scrolled down
+    ScrollDown { column: u16, row: u16 },
+    /// Moves, drags, releases and the other buttons
+    Unknown,
+}
+
+impl Mouse {
+    /// The column and row the event happened at
+    pub fn position(&self) -> Option<(u16, u16)> {
+        match *self {
+            Mouse::Click { column, row }
+            | Mouse::ScrollUp { column, row }
+            | Mouse::ScrollDown { column, row } => Some((column, row)),
+            Mouse::Unknown => None,
+        }
+    }
+}
+
+impl From<event::MouseEvent> for Mouse {
+    fn from(mouse: event::MouseEvent) -> Self {
+        let (column, row) = (mouse.column, mouse.row);
+        match mouse.kind {
+            MouseEventKind::Down(MouseButton::Left) => Mouse::Click { column, row },
+            MouseEventKind::ScrollUp => Mouse::ScrollUp { column, row },
+            MouseEventKind::ScrollDown => Mouse::ScrollDown { column, row },
+            _ => Mouse::Unknown,
+        }
+    }
+}
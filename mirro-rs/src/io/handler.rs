@@ -2,21 +2,20 @@ use std::sync::Arc;
 use std::time::Duration;
 
 use chrono::{DateTime, Utc};
-use linux_mirrors::archlinux::internal::ArchMirrors;
 use tokio::sync::mpsc::Receiver;
 use tracing::{debug, error, trace};
 
-use super::IoEvent;
+use super::{FetchEvent, IoEvent};
 use crate::app::App;
 use crate::Result;
 /// In the IO thread, we handle IO event without blocking the UI thread
 pub struct IoAsyncHandler {
     app: Arc<tokio::sync::Mutex<App>>,
-    mirrors_receiver: Receiver<ArchMirrors>,
+    mirrors_receiver: Receiver<FetchEvent>,
 }
 
 impl IoAsyncHandler {
-    pub fn new(app: Arc<tokio::sync::Mutex<App>>, mirrors_receiver: Receiver<ArchMirrors>) -> Self {
+    pub fn new(app: Arc<tokio::sync::Mutex<App>>, mirrors_receiver: Receiver<FetchEvent>) -> Self {
         Self {
             app,
             mirrors_receiver,
@@ -41,9 +40,17 @@ impl IoAsyncHandler {
     /// Get your mirrors here
     async fn do_initialize(&mut self) -> Result<()> {
         // get mirrors
-        while let Some(mirrors) = &self.mirrors_receiver.recv().await {
+        while let Some(event) = self.mirrors_receiver.recv().await {
             let mut app = self.app.lock().await;
-            app.update_mirrors(mirrors);
+            match event {
+                FetchEvent::Retrying(progress) => app.fetch_progress = Some(progress),
+                FetchEvent::Loaded(data) => {
+                    app.update_mirrors(&data.mirrors, data.provenance);
+                    app.fetch_error = data.error;
+                    app.fetch_note = data.note;
+                    app.fetch_progress = None;
+                }
+            }
         }
         let mut app = self.app.lock().await;
         app.initialized(); // we could update the app state
@@ -1,7 +1,30 @@
 use std::time::Duration;
 
+use linux_mirrors::archlinux::internal::ArchMirrors;
+
+use crate::app::state::Provenance;
+
 pub mod handler;
 
+/// The mirror status handed to the app once it is loaded
+#[derive(Debug, Clone)]
+pub struct MirrorData {
+    pub mirrors: ArchMirrors,
+    pub provenance: Provenance,
+    /// Why the status could not be fetched, if it was loaded from elsewhere
+    pub error: Option<String>,
+    /// What else the user should know about the status, which is not an error
+    pub note: Option<String>,
+}
+
+/// What the task fetching the mirror status reports to the app
+#[derive(Debug, Clone)]
+pub enum FetchEvent {
+    /// An attempt failed and another one is coming, with what the user should be told
+    Retrying(String),
+    Loaded(MirrorData),
+}
+
 #[derive(Debug, Clone)]
 pub enum IoEvent {
     Initialise,      // Launch to init application
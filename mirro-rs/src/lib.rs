@@ -1,10 +1,16 @@
 use std::{io::stdout, sync::Arc, time::Duration};
 
+use chrono::{DateTime, Utc};
+use clap::crate_name;
 use crossterm::{
     event::{DisableMouseCapture, EnableMouseCapture},
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
+use linux_mirrors::{
+    archlinux::{internal::ArchMirrors, ArchLinux},
+    Cache, Client, Error, Retry,
+};
 use tokio::sync::Mutex;
 use tui::{backend::CrosstermBackend, Terminal};
 
@@ -15,11 +21,82 @@ use self::{
 };
 
 pub mod app;
+pub mod cli;
 pub mod inputs;
 pub mod io;
 
 pub type Result<T> = std::result::Result<T, Box<dyn std::error::Error + Send + Sync>>;
 
+/// What went wrong fetching the mirror status, and what the user can do about it
+pub fn explain(error: &Error) -> String {
+    match error {
+        Error::Transport(e) => format!(
+            "could not reach archlinux.org, check your network connection ({})",
+            e
+        ),
+        Error::Timeout(_) => "archlinux.org took too long to answer, try again later".to_owned(),
+        Error::Status(status) if status.is_server_error() => format!(
+            "archlinux.org is having trouble ({}), try again later",
+            status
+        ),
+        Error::Status(status) => format!("archlinux.org answered {}", status),
+        Error::Decode { path, .. } => format!(
+            "the mirror status changed format at `{}`, {} may need an update",
+            path,
+            crate_name!()
+        ),
+        Error::UnsupportedVersion { found, .. } => format!(
+            "the mirror status is in schema version {}, update {} to read it",
+            found,
+            crate_name!()
+        ),
+        Error::InvalidUrl(e) => format!("invalid url: {}", e),
+        Error::Tls(e) => format!(
+            "could not set up tls, check your openssl installation: {}",
+            e
+        ),
+        Error::Config(e) => format!("check the [network] section of your config: {}", e),
+        Error::Io(e) => format!("could not use the cached mirror status: {}", e),
+    }
+}
+
+/// What to tell the user while fetching the mirror status is retried
+pub fn describe_retry(retry: &Retry) -> String {
+    format!(
+        "{}, retrying ({} of {}) in {:.1}s",
+        explain(retry.error),
+        retry.attempt,
+        retry.max_attempts,
+        retry.delay.as_secs_f64()
+    )
+}
+
+/// A note on the mirrors left out of `mirrors` because they were malformed, if there were any
+pub fn skipped(mirrors: &ArchMirrors) -> Option<String> {
+    match mirrors.skipped {
+        0 => None,
+        1 => Some("1 malformed mirror was left out".to_owned()),
+        skipped => Some(format!("{} malformed mirrors were left out", skipped)),
+    }
+}
+
+/// The Arch Linux provider fetching with `client`, caching the mirror status under
+/// `$XDG_CACHE_HOME/mirro-rs`
+pub fn arch_linux(client: Client) -> ArchLinux {
+    match Cache::xdg(crate_name!()) {
+        Some(cache) => ArchLinux::new(client).with_cache(cache),
+        None => ArchLinux::new(client),
+    }
+}
+
+/// The status cached by the last successful fetch of [`arch_linux`] and when it was fetched
+pub fn cached_status() -> linux_mirrors::Result<Option<(ArchMirrors, DateTime<Utc>)>> {
+    match Cache::xdg(crate_name!()) {
+        Some(cache) => ArchLinux::cached_in(&cache),
+        None => Ok(None),
+    }
+}
+
 pub async fn start_ui(app: Arc<Mutex<App>>) -> Result<()> {
     let mut stdout = stdout();
     execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
@@ -56,6 +133,11 @@ pub async fn start_ui(app: Arc<Mutex<App>>) -> Result<()> {
              * */
             InputEvent::Input(key) => app.do_action(key).await,
 
+            /*
+             * Clicks and the wheel act on the widget under the pointer
+             * */
+            InputEvent::Mouse(mouse) => app.do_mouse(mouse).await,
+
             /*
              * No user input, however we may have to be doing some processing here, so mutation is
              * also a possibility
@@ -1,22 +1,32 @@
+use clap::ArgMatches;
 use mirro_rs::{
-    app::{config::MirrorsConfig, App},
-    io::handler::IoAsyncHandler,
-    start_ui,
+    app::{config::MirrorsConfig, state::Provenance, App},
+    arch_linux, cached_status, cli, describe_retry, explain,
+    io::{handler::IoAsyncHandler, FetchEvent, MirrorData},
+    skipped, start_ui,
 };
 use std::{sync::Arc, time::Duration};
 use tokio::sync::Mutex;
-use tracing::error;
+use tracing::{error, info, warn};
 use xdg::BaseDirectories;
 
 #[tokio::main]
 async fn main() -> mirro_rs::Result<()> {
-    let (_guard, config) = initialise_app();
+    let (guard, config, matches) = initialise_app();
+    if let Some(args) = matches.subcommand_matches(cli::SUBCOMMAND) {
+        let exit = cli::run(args, &config).await;
+        // flush the logs, exit skips destructors
+        drop(guard);
+        std::process::exit(exit as i32);
+    }
     let (mirrors_tx, mirrors_rx) = tokio::sync::mpsc::channel(16);
 
     /* Sharing the IoEvents between threads */
     let (sync_io_tx, mut sync_io_rx) = tokio::sync::mpsc::channel(100);
 
     // Since application state can be accessed and mutated across threads
+    let client = config.client();
+    let retry = config.retry_policy();
     let app = Arc::new(Mutex::new(App::new(sync_io_tx.clone(), config)));
     let app_ui = Arc::clone(&app);
     let app_clock = Arc::clone(&app);
@@ -29,15 +39,57 @@ async fn main() -> mirro_rs::Result<()> {
         }
     });
     tokio::spawn(async move {
-        let mirrors = match linux_mirrors::archlinux::mirrors().await {
-            Ok(res) => res,
+        let fetched = match client {
+            Ok(client) => {
+                arch_linux(client)
+                    .with_retry(retry)
+                    .fetch_reporting(|retry| {
+                        warn!("{retry}");
+                        let _ = mirrors_tx.try_send(FetchEvent::Retrying(describe_retry(retry)));
+                    })
+                    .await
+            }
+            Err(e) => Err(e),
+        };
+        let data = match fetched {
+            Ok(mirrors) => MirrorData {
+                note: skipped(&mirrors),
+                mirrors,
+                provenance: Provenance::Live,
+                error: None,
+            },
             Err(e) => {
                 error!("{e}");
-                let local_file = include_str!("../../assets/arch_mirrors.json");
-                serde_json::from_str(local_file).expect("could not load backup file")
+                let error = Some(explain(&e));
+                // the cache can still be read when the client is misconfigured
+                match cached_status() {
+                    Ok(Some((mirrors, fetched))) => {
+                        info!("using the mirror status cached at {}", fetched);
+                        MirrorData {
+                            note: skipped(&mirrors),
+                            mirrors,
+                            provenance: Provenance::Cached,
+                            error,
+                        }
+                    }
+                    cached => {
+                        if let Err(e) = cached {
+                            error!("{e}");
+                        }
+                        let local_file = include_str!("../../assets/arch_mirrors.json");
+                        let mirrors =
+                            serde_json::from_str(local_file).expect("could not load backup file");
+                        MirrorData {
+                            note: skipped(&mirrors),
+                            mirrors,
+                            provenance: Provenance::Bundled,
+                            error,
+                        }
+                    }
+                }
             }
         };
-        mirrors_tx.send(mirrors).await
+        mirrors_tx.send(FetchEvent::Loaded(data)).await
     });
     tokio::spawn(async move {
         loop {
@@ -52,7 +104,11 @@ async fn main() -> mirro_rs::Result<()> {
     Ok(())
 }
 
-fn initialise_app() -> (tracing_appender::non_blocking::WorkerGuard, MirrorsConfig) {
+fn initialise_app() -> (
+    tracing_appender::non_blocking::WorkerGuard,
+    MirrorsConfig,
+    ArgMatches,
+) {
     let m = clap::app_from_crate!()
         .arg(
             clap::Arg::new("log level")
@@ -68,6 +124,14 @@ fn initialise_app() -> (tracing_appender::non_blocking::WorkerGuard, MirrorsConf
                 .long("config")
                 .help("Read custom config.toml file [uses $XDG_CONFIG_HOME if not specified]"),
         )
+        .arg(
+            clap::Arg::new("output")
+                .takes_value(true)
+                .short('o')
+                .long("output")
+                .help("Write the mirrorlist to this file [default: /etc/pacman.d/mirrorlist]"),
+        )
+        .subcommand(cli::subcommand())
         .get_matches();
     let mut log_valid = true;
     let log_level = match m.value_of("log level") {
@@ -85,7 +149,7 @@ fn initialise_app() -> (tracing_appender::non_blocking::WorkerGuard, MirrorsConf
         None => tracing::Level::DEBUG,
     };
 
-    let configuration: MirrorsConfig = match m.value_of("config") {
+    let mut configuration: MirrorsConfig = match m.value_of("config") {
         None => try_default(),
         Some(conf) => match std::fs::read_to_string(conf) {
             Ok(str) => match toml::from_str(&str) {
@@ -101,8 +165,16 @@ fn initialise_app() -> (tracing_appender::non_blocking::WorkerGuard, MirrorsConf
             }
         },
     };
+    if let Some(output) = m.value_of("output") {
+        configuration.set_export_path(output);
+    }
+    configuration.state = BaseDirectories::with_prefix(clap::crate_name!())
+        .ok()
+        .map(|dir| dir.get_state_file("sort.toml"));
+    configuration.load_sort();
 
-    setup_logger((log_level, log_valid), configuration)
+    let (guard, configuration) = setup_logger((log_level, log_valid), configuration);
+    (guard, configuration, m)
 }
 
 fn try_default() -> MirrorsConfig {